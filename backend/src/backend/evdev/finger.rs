@@ -1,6 +1,6 @@
 use super::{
-    super::super::{parse::FingerData, utility::ErrToString},
-    PushEvent, WithAbs,
+    super::super::{metrics::METRICS, parse::FingerData, utility::ErrToString},
+    DeviceIdentity, PushEvent, WithAbs, WithTimestamp,
 };
 
 use evdev::{
@@ -8,6 +8,7 @@ use evdev::{
     AbsInfo, AbsoluteAxisType, AttributeSet, BusType, InputEvent, InputId, Key, PropType,
     UinputAbsSetup,
 };
+use tracing::{info, trace};
 
 const ABS_MT_SLOT: u16 = AbsoluteAxisType::ABS_MT_SLOT.0;
 const ABS_MT_POSITION_X: u16 = AbsoluteAxisType::ABS_MT_POSITION_X.0;
@@ -29,17 +30,24 @@ pub struct FingerBackend {
     current_down: bool,
     inputs: Vec<InputEvent>,
     touch_trackings: [i32; 12],
+    touch_slots: [i32; 12],
     touch_active: [bool; 12],
     touch_pos: [(i32, i32); 12],
 }
 
 impl FingerBackend {
     // Create new evdev device
-    pub fn new() -> Result<Self, String> {
+    pub fn new(identity: &DeviceIdentity) -> Result<Self, String> {
+        let name = identity.device_name("pendroid-touchpad");
         let mut device = VirtualDeviceBuilder::new()
             .err_tostring()?
-            .name("pendroid-touchpad")
-            .input_id(InputId::new(BusType::BUS_USB, 0u16, 1333u16, 1u16))
+            .name(&name)
+            .input_id(InputId::new(
+                BusType::BUS_USB,
+                0u16,
+                identity.product_id(1333u16)?,
+                1u16,
+            ))
             .with_abs(&[
                 // TOOL INFO
                 UinputAbsSetup::new(
@@ -84,12 +92,13 @@ impl FingerBackend {
                 PropType::BUTTONPAD,
             ]))
             .err_tostring()?
+            .with_timestamp()?
             .build()
             .err_tostring()?;
 
         for path in device.enumerate_dev_nodes_blocking().err_tostring()? {
             let path = path.err_tostring()?;
-            println!("Available as {}", path.display());
+            info!("Available as {}", path.display());
         }
 
         Ok(Self {
@@ -99,6 +108,7 @@ impl FingerBackend {
             current_down: false,
             touch_active: [false; 12],
             touch_trackings: [-1i32; 12],
+            touch_slots: [-1i32; 12],
             touch_pos: [(0, 0); 12],
         })
     }
@@ -111,8 +121,13 @@ impl FingerBackend {
         }
     }
 
-    pub fn process(&mut self, finger_data: &FingerData) -> Result<(), String> {
+    pub fn process(
+        &mut self,
+        finger_data: &FingerData,
+        sample_time: Option<u64>,
+    ) -> Result<(), String> {
         self.inputs.clear();
+        self.inputs.push_timestamp(sample_time);
 
         // MT event
         for (index, touch) in finger_data.touchs.iter().enumerate() {
@@ -130,6 +145,7 @@ impl FingerBackend {
                 self.inputs.push_abs_event(ABS_MT_POSITION_Y, touch.y);
             }
             self.touch_pos[index] = (touch.x, touch.y);
+            self.touch_slots[index] = touch.slot;
 
             // Update ABS_MT_TRACKING_ID
             if self.touch_trackings[index] != touch.tracking_id {
@@ -167,7 +183,44 @@ impl FingerBackend {
             break;
         }
 
-        self.device.emit(self.inputs.as_slice()).err_tostring()?;
+        emit(&mut self.device, &self.inputs)?;
         Ok(())
     }
+
+    // Lift every finger that is still down
+    pub fn reset(&mut self) -> Result<(), String> {
+        self.inputs.clear();
+
+        for index in 0..self.touch_trackings.len() {
+            if self.touch_trackings[index] == -1 {
+                continue;
+            }
+            self.update_slot(self.touch_slots[index]);
+            self.touch_trackings[index] = -1;
+            self.inputs.push_abs_event(ABS_MT_TRACKING_ID, -1);
+        }
+
+        for (index, key) in TOUCHS.iter().enumerate() {
+            if self.touch_active[index] {
+                self.touch_active[index] = false;
+                self.inputs.push_key(key, 0);
+            }
+        }
+
+        if self.current_down {
+            self.current_down = false;
+            self.inputs.push_key(&Key::BTN_TOUCH, 0);
+        }
+
+        emit(&mut self.device, &self.inputs)?;
+        Ok(())
+    }
+}
+
+// Write one report to the device, traced event by event
+fn emit(device: &mut VirtualDevice, events: &[InputEvent]) -> Result<(), String> {
+    trace!(?events, "emit");
+    device.emit(events).err_tostring()?;
+    METRICS.events("touchpad", events.len());
+    Ok(())
 }
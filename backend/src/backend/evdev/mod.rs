@@ -1,4 +1,9 @@
-use evdev::{uinput::VirtualDeviceBuilder, EventType, InputEvent, Key, UinputAbsSetup};
+use std::time::Duration;
+
+use evdev::{
+    uinput::VirtualDeviceBuilder, AttributeSet, EventType, InputEvent, Key, MiscType,
+    UinputAbsSetup,
+};
 
 use super::super::{
     parse::{action_parse, ActionType},
@@ -8,8 +13,8 @@ use super::super::{
 mod finger;
 mod stylus;
 
-use finger::FingerBackend;
-use stylus::StylusBackend;
+pub use finger::FingerBackend;
+pub use stylus::StylusBackend;
 
 trait WithAbs<'a> {
     fn with_abs(self, abs_list: &[UinputAbsSetup]) -> Result<VirtualDeviceBuilder<'a>, String>;
@@ -24,11 +29,23 @@ impl<'a> WithAbs<'a> for VirtualDeviceBuilder<'a> {
     }
 }
 
+trait WithTimestamp<'a> {
+    fn with_timestamp(self) -> Result<VirtualDeviceBuilder<'a>, String>;
+}
+impl<'a> WithTimestamp<'a> for VirtualDeviceBuilder<'a> {
+    fn with_timestamp(self) -> Result<VirtualDeviceBuilder<'a>, String> {
+        self.with_msc(&AttributeSet::from_iter([MiscType::MSC_TIMESTAMP]))
+            .err_tostring()
+    }
+}
+
 pub type EventList = Vec<InputEvent>;
 pub trait PushEvent {
     fn push_abs_event(&mut self, code: u16, value: i32);
     // fn push_rel_event(&mut self, code: u16, value: i32);
     fn push_key(&mut self, code: &Key, value: i32);
+    // Sample time in microseconds, the kernel lets it wrap around
+    fn push_timestamp(&mut self, micros: Option<u64>);
 }
 impl PushEvent for EventList {
     #[inline]
@@ -39,6 +56,13 @@ impl PushEvent for EventList {
     fn push_key(&mut self, code: &Key, value: i32) {
         self.push(InputEvent::new(EventType::KEY, code.code(), value));
     }
+    #[inline]
+    fn push_timestamp(&mut self, micros: Option<u64>) {
+        if let Some(micros) = micros {
+            let code = MiscType::MSC_TIMESTAMP.0;
+            self.push(InputEvent::new(EventType::MISC, code, micros as u32 as i32));
+        }
+    }
 }
 pub trait GetInputs {
     fn get_inputs(&mut self) -> &mut EventList;
@@ -53,6 +77,56 @@ where
     fn push_key(&mut self, code: &Key, value: i32) {
         self.get_inputs().push_key(code, value);
     }
+    fn push_timestamp(&mut self, micros: Option<u64>) {
+        self.get_inputs().push_timestamp(micros);
+    }
+}
+
+// Tells devices of different clients apart. Index 0 is the shared set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub index: u16,
+    pub label: Option<String>,
+}
+
+impl DeviceIdentity {
+    // Highest per-client index, keeps every product id of a set distinct
+    pub const MAX_INDEX: u16 = 255;
+
+    pub fn new(index: u16, label: Option<&str>) -> Self {
+        // uinput names are limited to 80 bytes, keep labels short and plain
+        let label = label
+            .map(|label| {
+                label
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+                    .take(32)
+                    .collect::<String>()
+            })
+            .filter(|label| !label.is_empty());
+        Self { index, label }
+    }
+
+    // pendroid-stylus, pendroid-stylus-2 or pendroid-stylus-2-alice
+    pub fn device_name(&self, base: &str) -> String {
+        match (self.index, &self.label) {
+            (0, None) => String::from(base),
+            (index, None) => format!("{base}-{index}"),
+            (index, Some(label)) => format!("{base}-{index}-{label}"),
+        }
+    }
+
+    // Every index gets its own pair of product ids. An index past
+    // MAX_INDEX would collide with another set, so it is an error.
+    pub fn product_id(&self, base: u16) -> Result<u16, String> {
+        if self.index > Self::MAX_INDEX {
+            return Err(format!("device index {} is too large", self.index));
+        }
+        self.index
+            .checked_mul(2)
+            .and_then(|offset| base.checked_add(offset))
+            .ok_or_else(|| format!("product id {base} overflows for index {}", self.index))
+    }
 }
 
 pub struct InputBackend {
@@ -61,18 +135,55 @@ pub struct InputBackend {
 }
 impl InputBackend {
     pub fn new() -> Result<Self, String> {
+        Self::with_identity(&DeviceIdentity::default())
+    }
+
+    pub fn with_identity(identity: &DeviceIdentity) -> Result<Self, String> {
         Ok(Self {
-            stylus: StylusBackend::new()?,
-            finger: FingerBackend::new()?,
+            stylus: StylusBackend::new(identity)?,
+            finger: FingerBackend::new(identity)?,
         })
     }
 
-    pub fn execute_text(&mut self, text: String) -> Result<(), String> {
-        let action = action_parse(text)?;
+    // Report the pen this far ahead of where it is, None turns it off
+    pub fn set_prediction(&mut self, lead: Option<Duration>) {
+        self.stylus.set_prediction(lead);
+    }
+
+    pub fn execute(&mut self, action: &ActionType) -> Result<(), String> {
+        self.execute_at(action, None)
+    }
+
+    // sample_time is when the client took the sample, in server clock
+    // microseconds. It is reported as MSC_TIMESTAMP with the events.
+    pub fn execute_at(
+        &mut self,
+        action: &ActionType,
+        sample_time: Option<u64>,
+    ) -> Result<(), String> {
         match action {
-            ActionType::Finger(finger_data) => self.finger.process(&finger_data),
-            ActionType::Stylus(stylus_data) => self.stylus.process(&stylus_data),
+            ActionType::Finger(finger_data) => self.finger.process(finger_data, sample_time),
+            ActionType::Stylus(stylus_data) => self.stylus.process(stylus_data, sample_time),
+            ActionType::StylusBatch(batch_data) => {
+                self.stylus.process_batch(batch_data, sample_time)
+            }
             ActionType::Screen(_screen) => Ok(()),
+            ActionType::Heartbeat(_)
+            | ActionType::Clock(_)
+            | ActionType::Latency(_)
+            | ActionType::Ack(_)
+            | ActionType::Error(_) => Ok(()),
         }
     }
+
+    pub fn execute_text(&mut self, text: String) -> Result<(), String> {
+        self.execute(&action_parse(text)?)
+    }
+
+    // Release everything still pressed on both devices
+    pub fn reset(&mut self) -> Result<(), String> {
+        let stylus = self.stylus.reset();
+        let finger = self.finger.reset();
+        stylus.and(finger)
+    }
 }
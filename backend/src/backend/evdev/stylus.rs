@@ -1,8 +1,16 @@
-use std::sync::LazyLock;
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
 
 use super::{
-    super::super::{parse::StylusData, utility::ErrToString},
-    EventList, GetInputs, PushEvent, WithAbs,
+    super::super::{
+        metrics::METRICS,
+        parse::{StylusBatchData, StylusData},
+        predictor::StylusPredictor,
+        utility::ErrToString,
+    },
+    DeviceIdentity, EventList, GetInputs, PushEvent, WithAbs, WithTimestamp,
 };
 
 use evdev::{
@@ -10,6 +18,7 @@ use evdev::{
     AbsInfo, AbsoluteAxisType, AttributeSet, BusType, InputEvent, InputId, Key, PropType,
     UinputAbsSetup,
 };
+use tracing::{info, trace};
 
 const ABS_X: u16 = AbsoluteAxisType::ABS_X.0;
 const ABS_Y: u16 = AbsoluteAxisType::ABS_Y.0;
@@ -23,6 +32,9 @@ pub struct StylusBackend {
     current_hover: bool,
     current_button: bool,
     inputs: EventList,
+    predictor: Option<StylusPredictor>,
+    // Clock for prediction when the client sends no timestamps
+    created: Instant,
 }
 
 static RUBBER_OFF: LazyLock<EventList> = LazyLock::new(|| {
@@ -44,11 +56,17 @@ impl GetInputs for StylusBackend {
 
 impl StylusBackend {
     // Create new evdev device
-    pub fn new() -> Result<Self, String> {
+    pub fn new(identity: &DeviceIdentity) -> Result<Self, String> {
+        let name = identity.device_name("pendroid-stylus");
         let mut device = VirtualDeviceBuilder::new()
             .err_tostring()?
-            .name("pendroid-stylus")
-            .input_id(InputId::new(BusType::BUS_USB, 0u16, 1332u16, 1u16))
+            .name(&name)
+            .input_id(InputId::new(
+                BusType::BUS_USB,
+                0u16,
+                identity.product_id(1332u16)?,
+                1u16,
+            ))
             .with_abs(&[
                 // ABS PRESSURE
                 UinputAbsSetup::new(
@@ -83,12 +101,13 @@ impl StylusBackend {
             .err_tostring()?
             .with_properties(&AttributeSet::from_iter([PropType::POINTER]))
             .err_tostring()?
+            .with_timestamp()?
             .build()
             .err_tostring()?;
 
         for path in device.enumerate_dev_nodes_blocking().err_tostring()? {
             let path = path.err_tostring()?;
-            println!("Available as {}", path.display());
+            info!("Available as {}", path.display());
         }
 
         Ok(Self {
@@ -97,17 +116,36 @@ impl StylusBackend {
             current_down: false,
             current_hover: false,
             current_button: false,
+            predictor: None,
+            created: Instant::now(),
         })
     }
 
-    pub fn process(&mut self, pen_data: &StylusData) -> Result<(), String> {
+    // Report the pen this far ahead of where it is, None turns it off
+    pub fn set_prediction(&mut self, lead: Option<Duration>) {
+        self.predictor = lead.map(StylusPredictor::new);
+    }
+
+    pub fn process(
+        &mut self,
+        pen_data: &StylusData,
+        sample_time: Option<u64>,
+    ) -> Result<(), String> {
         let hover_changed = pen_data.hover != self.current_hover;
         let button_changed = pen_data.button != self.current_button;
         self.inputs.clear();
+        self.push_timestamp(sample_time);
 
         // Report position and pressure
-        self.push_abs_event(ABS_X, pen_data.x);
-        self.push_abs_event(ABS_Y, pen_data.y);
+        let (x, y) = match &mut self.predictor {
+            Some(predictor) => {
+                let time = sample_time.unwrap_or_else(|| self.created.elapsed().as_micros() as u64);
+                predictor.predict(pen_data, time)
+            }
+            None => (pen_data.x, pen_data.y),
+        };
+        self.push_abs_event(ABS_X, x);
+        self.push_abs_event(ABS_Y, y);
         self.push_abs_event(ABS_PRESSURE, pen_data.pressure);
         self.push_abs_event(ABS_TILT_X, pen_data.tilt_x);
         self.push_abs_event(ABS_TILT_Y, pen_data.tilt_y);
@@ -117,13 +155,13 @@ impl StylusBackend {
             if pen_data.button {
                 if !hover_changed {
                     // Disable old tool
-                    self.device.emit(&PENCIL_OFF).err_tostring()?;
+                    emit(&mut self.device, &PENCIL_OFF)?;
                 }
                 self.push_key(&Key::BTN_TOOL_RUBBER, 1);
             } else {
                 if !hover_changed {
                     // Disable old tool
-                    self.device.emit(&RUBBER_OFF).err_tostring()?;
+                    emit(&mut self.device, &RUBBER_OFF)?;
                 }
                 self.push_key(&Key::BTN_TOOL_PENCIL, 1);
             }
@@ -156,7 +194,73 @@ impl StylusBackend {
             self.current_down = pen_data.down;
         }
 
-        self.device.emit(&self.inputs).err_tostring()?;
+        emit(&mut self.device, &self.inputs)?;
+        Ok(())
+    }
+
+    // Emit every sample as its own report, oldest first. sample_time is the
+    // batch timestamp, each sample is age microseconds before it.
+    pub fn process_batch(
+        &mut self,
+        batch_data: &StylusBatchData,
+        sample_time: Option<u64>,
+    ) -> Result<(), String> {
+        for sample in &batch_data.samples {
+            let pen_data = StylusData {
+                x: sample.x,
+                y: sample.y,
+                tilt_x: sample.tilt_x,
+                tilt_y: sample.tilt_y,
+                pressure: sample.pressure,
+                down: batch_data.down,
+                hover: batch_data.hover,
+                button: batch_data.button,
+            };
+            let time = sample_time.map(|time| time.saturating_sub(sample.age as u64));
+            self.process(&pen_data, time)?;
+        }
+        Ok(())
+    }
+
+    // Lift the pen and put the tool away, as if it left the screen
+    pub fn reset(&mut self) -> Result<(), String> {
+        self.inputs.clear();
+        if let Some(predictor) = &mut self.predictor {
+            predictor.reset();
+        }
+        if self.current_down {
+            self.push_key(
+                if self.current_button {
+                    &Key::BTN_STYLUS2
+                } else {
+                    &Key::BTN_STYLUS
+                },
+                0,
+            );
+            self.current_down = false;
+        }
+        if self.current_hover {
+            self.push_key(
+                if self.current_button {
+                    &Key::BTN_TOOL_RUBBER
+                } else {
+                    &Key::BTN_TOOL_PENCIL
+                },
+                0,
+            );
+            self.current_hover = false;
+        }
+        self.push_abs_event(ABS_PRESSURE, 0);
+
+        emit(&mut self.device, &self.inputs)?;
         Ok(())
     }
 }
+
+// Write one report to the device, traced event by event
+fn emit(device: &mut VirtualDevice, events: &[InputEvent]) -> Result<(), String> {
+    trace!(?events, "emit");
+    device.emit(events).err_tostring()?;
+    METRICS.events("stylus", events.len());
+    Ok(())
+}
@@ -1,4 +1,3 @@
 mod evdev;
 
-#[cfg(target_os = "linux")]
-pub use evdev::InputBackend;
+pub use evdev::{DeviceIdentity, FingerBackend, InputBackend, StylusBackend};
@@ -0,0 +1,202 @@
+use std::{
+    io::{stdin, BufRead},
+    net::TcpStream,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use pendroid::{
+    action_encode,
+    script::{self, Point},
+};
+use tokio_tungstenite::tungstenite::{connect, stream::MaybeTlsStream, Message, WebSocket};
+
+const USAGE: &str = "\
+Usage: pendroid-client [options] <command> [args]
+
+Commands:
+  line <x0> <y0> <x1> <y1>             Draw a straight stylus line
+  circle <cx> <cy> <radius>            Draw a stylus circle
+  bezier <x0> <y0> ... <x3> <y3>       Draw a cubic bezier stroke
+  tap <x> <y>                          Tap with one finger
+  swipe <fingers> <x0> <y0> <x1> <y1>  Swipe with several fingers
+  pinch <cx> <cy> <r0> <r1>            Pinch two fingers from r0 to r1
+  stdin                                Send protocol lines read from stdin
+
+Options:
+  --url <url>                          Backend address (default ws://localhost:57362)
+  --rate <hz>                          Samples per second (default 120)
+  --pressure <value>                   Stylus pressure, 0 ~ 4096 (default 2048)
+  --steps <count>                      Samples per stroke (default 60)
+  --spacing <px>                       Distance between swipe fingers (default 200)";
+
+fn option_value<T: FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    name: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("option {name} requires a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value} for {name}"))
+}
+
+struct Options {
+    url: String,
+    rate: f64,
+    pressure: i32,
+    steps: u32,
+    spacing: i32,
+    command: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            url: String::from("ws://localhost:57362"),
+            rate: 120.0,
+            pressure: 2048,
+            steps: 60,
+            spacing: 200,
+            command: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--url" => options.url = option_value(&mut args, &arg)?,
+                "--rate" => options.rate = option_value(&mut args, &arg)?,
+                "--pressure" => options.pressure = option_value(&mut args, &arg)?,
+                "--steps" => options.steps = option_value(&mut args, &arg)?,
+                "--spacing" => options.spacing = option_value(&mut args, &arg)?,
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => options.command.push(arg),
+            }
+        }
+        if options.rate <= 0.0 {
+            return Err(String::from("--rate must be positive"));
+        }
+        if !(0..=4096).contains(&options.pressure) {
+            return Err(String::from("--pressure must be between 0 and 4096"));
+        }
+        Ok(options)
+    }
+
+    fn numbers<const N: usize>(&self) -> Result<[i32; N], String> {
+        let args = &self.command[1..];
+        if args.len() != N {
+            return Err(format!(
+                "{} takes {N} arguments, got {}\n\n{USAGE}",
+                self.command[0],
+                args.len()
+            ));
+        }
+        let mut numbers = [0i32; N];
+        for (number, arg) in numbers.iter_mut().zip(args) {
+            *number = arg.parse().map_err(|_| format!("invalid number {arg}"))?;
+        }
+        Ok(numbers)
+    }
+
+    // Protocol lines for the requested gesture
+    fn script(&self) -> Result<Vec<String>, String> {
+        let Some(command) = self.command.first() else {
+            return Err(String::from(USAGE));
+        };
+        let actions = match command.as_str() {
+            "line" => {
+                let [x0, y0, x1, y1] = self.numbers()?;
+                let path = script::line((x0, y0), (x1, y1), self.steps);
+                script::stylus_stroke(&path, self.pressure)
+            }
+            "circle" => {
+                let [cx, cy, radius] = self.numbers()?;
+                let path = script::circle((cx, cy), radius, self.steps);
+                script::stylus_stroke(&path, self.pressure)
+            }
+            "bezier" => {
+                let [x0, y0, x1, y1, x2, y2, x3, y3] = self.numbers()?;
+                let points: [Point; 4] = [(x0, y0), (x1, y1), (x2, y2), (x3, y3)];
+                let path = script::bezier(points, self.steps);
+                script::stylus_stroke(&path, self.pressure)
+            }
+            "tap" => {
+                let [x, y] = self.numbers()?;
+                script::tap((x, y), 1)
+            }
+            "swipe" => {
+                let [fingers, x0, y0, x1, y1] = self.numbers()?;
+                script::swipe(
+                    fingers.max(1) as u32,
+                    (x0, y0),
+                    (x1, y1),
+                    self.spacing,
+                    self.steps,
+                    1,
+                )
+            }
+            "pinch" => {
+                let [cx, cy, r0, r1] = self.numbers()?;
+                script::pinch((cx, cy), r0, r1, self.steps, 1)
+            }
+            _ => return Err(format!("unknown command {command}\n\n{USAGE}")),
+        };
+        Ok(actions.iter().map(action_encode).collect())
+    }
+}
+
+// Send lines paced by interval, then close the connection
+fn play(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    lines: impl Iterator<Item = String>,
+    interval: Option<Duration>,
+) -> Result<(), String> {
+    for line in lines {
+        socket
+            .send(Message::text(line))
+            .map_err(|err| err.to_string())?;
+        if let Some(interval) = interval {
+            thread::sleep(interval);
+        }
+    }
+    socket.close(None).map_err(|err| err.to_string())?;
+
+    // Wait for the close handshake, replies from the backend are ignored
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+fn run() -> Result<(), String> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    let from_stdin = options
+        .command
+        .first()
+        .is_some_and(|command| command == "stdin");
+    // None reads protocol lines from stdin instead
+    let script = if from_stdin {
+        None
+    } else {
+        Some(options.script()?)
+    };
+    let interval = Duration::from_secs_f64(1.0 / options.rate);
+
+    let (mut socket, _) = connect(options.url.as_str()).map_err(|err| err.to_string())?;
+    match script {
+        Some(lines) => play(&mut socket, lines.into_iter(), Some(interval)),
+        None => play(
+            &mut socket,
+            stdin().lock().lines().map_while(Result::ok),
+            None,
+        ),
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,24 @@
+//! Pendroid input injection
+//!
+//! Parses and encodes the pendroid protocol (`S`, `B`, `F`, `V`, `H`, `C`, `L`, `A`, `E` actions)
+//! in [`parse`] and drives uinput devices through [`InputBackend`]. The common protocol items are
+//! also re-exported here.
+
+#[cfg(target_os = "linux")]
+mod backend;
+pub mod metrics;
+pub mod parse;
+mod predictor;
+pub mod script;
+#[cfg(target_os = "linux")]
+pub mod server;
+mod utility;
+
+#[cfg(target_os = "linux")]
+pub use backend::{DeviceIdentity, FingerBackend, InputBackend, StylusBackend};
+pub use parse::{
+    action_encode, action_encode_meta, action_parse, action_parse_meta, AckData, ActionMeta,
+    ActionType, ClockData, ErrorData, ErrorKind, FingerData, HeartbeatData, LatencyData,
+    StylusBatchData, StylusData, StylusSample, Touch, ViewData,
+};
+pub use predictor::StylusPredictor;
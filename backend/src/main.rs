@@ -1,51 +1,184 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use ws::{Error as WsError, Factory, Handler, Message, Sender, WebSocket};
+use pendroid::server::{serve, AuditLog, ServerConfig};
+use tokio::runtime::Builder;
+use tracing_subscriber::EnvFilter;
 
-mod backend;
-mod parse;
-mod utility;
+const USAGE: &str = "\
+Usage: pendroid [options]
 
-use backend::InputBackend;
-use utility::ErrToString;
+Options:
+  --listen <address>       Address to listen on (default localhost:57362)
+  --udp <address>          Also accept input datagrams on this address
+  --tcp <address>          Also accept newline-delimited protocol lines
+  --unix <path>            Same as --tcp, on a Unix socket
+  --tls                    Serve wss:// with a self-signed certificate,
+                           generated on first run. Its SHA-256
+                           fingerprint is printed for pinning in the app
+  --tls-dir <path>         Where the certificate is kept
+                           (default ~/.config/pendroid)
+  --mdns                   Advertise the server on the local network as
+                           _pendroid._tcp so the app can find it
+  --allow-origin <origin>  Let web pages from this origin connect, such as
+                           https://example.com. Can be repeated. Other
+                           browser connections are refused, clients
+                           without an Origin header are always allowed
+  --user <name>            Create the devices, then switch to this user and
+                           sandbox the process with Landlock and seccomp.
+                           Not possible with per-client devices
+  --group <name>           Group to switch to (default the user's group)
+  --audit-log <path>       Append one JSON line per connection, accepted or
+                           refused, with its peer, origin, label, duration,
+                           message counts and why it ended
+  --audit-max-size <bytes> Rotate the audit log at this size, to <path>.1
+                           and so on (default 10485760)
+  --audit-keep <count>     Rotated audit logs to keep (default 5)
+  --clients <policy>       What to do with concurrent clients: reject,
+                           takeover or per-client (default takeover).
+                           With per-client, every connection gets its own
+                           devices, named after ws://host:port/?label=name
+  --ping-interval <secs>   How often to ping clients (default 5)
+  --timeout <secs>         Disconnect clients silent for this long and
+                           release their input (default 15)
+  --report-interval <secs> How often to log latency percentiles and send
+                           them to the client (default 10)
+  --jitter-buffer <ms>     Pace input by client timestamps, holding it for
+                           at least this long and more on jittery networks
+                           (default off)
+  --predict <ms>           Report the stylus this far ahead of the samples
+                           to hide latency (default off)
+  --max-message <bytes>    Longest message a client may send (default 4096)
+  --max-rate <count>       Messages per second a client may send, with
+                           bursts of up to a second (default 1000)
+  --max-fields <count>     Most fields in one message (default 512)
+  --max-errors <count>     Messages over these limits are answered with an
+                           E error. Clients with more than this many in a
+                           second are disconnected (default 100)
+  --metrics <address>      Serve Prometheus metrics at
+                           http://<address>/metrics: messages by type,
+                           parse errors by field, device errors and events,
+                           connections, sample latency and stylus rate
+  --log <filter>           Log verbosity, per module if needed, such as
+                           warn,pendroid::server=info. At trace,
+                           pendroid::parse logs every parsed action and
+                           pendroid::backend every emitted input event
+                           (default info, or PENDROID_LOG)
 
-// Connection
-struct PenWsConnection {
-    backend: Rc<RefCell<InputBackend>>,
+Under systemd socket activation, sockets named udp, tcp, unix and metrics
+with FileDescriptorName= replace --udp, --tcp, --unix and --metrics, and
+any other socket replaces --listen. Readiness and watchdog pings go to
+NOTIFY_SOCKET.";
+
+fn seconds(text: &str) -> Result<Duration, String> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|secs| *secs > 0.0)
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid duration {text}"))
 }
-impl Handler for PenWsConnection {
-    fn on_message(&mut self, msg: Message) -> Result<(), WsError> {
-        // Ok(self.backend)
-        let Message::Text(text) = msg else {
-            println!("Got unexpected client data");
-            return Ok(());
-        };
-        if let Err(err) = (*self.backend).borrow_mut().execute_text(text) {
-            println!("{err}");
-        };
-        Ok(())
-    }
+
+fn millis(text: &str) -> Result<Duration, String> {
+    seconds(text).map(|duration| duration / 1000)
 }
 
-struct PenWsFactory {
-    backend: Rc<RefCell<InputBackend>>,
+fn count(text: &str) -> Result<u32, String> {
+    text.parse::<u32>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("invalid count {text}"))
 }
-impl Factory for PenWsFactory {
-    type Handler = PenWsConnection;
 
-    fn connection_made(&mut self, _: Sender) -> Self::Handler {
-        PenWsConnection {
-            backend: self.backend.clone(),
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<ServerConfig, String> {
+    let mut config = ServerConfig::default();
+    let mut audit_log: Option<PathBuf> = None;
+    let mut audit_max_size = 10 << 20;
+    let mut audit_keep = 5;
+    let mut log = env::var("PENDROID_LOG").unwrap_or_else(|_| String::from("info"));
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("option {arg} requires a value"))
+        };
+        match arg.as_str() {
+            "--listen" => config.listen = value()?,
+            "--udp" => config.udp = Some(value()?),
+            "--tls" => config.tls = true,
+            "--mdns" => config.mdns = true,
+            "--tls-dir" => config.tls_dir = Some(value()?.into()),
+            "--tcp" => config.tcp = Some(value()?),
+            "--unix" => config.unix = Some(value()?.into()),
+            "--allow-origin" => config.allowed_origins.push(value()?),
+            "--user" => config.user = Some(value()?),
+            "--group" => config.group = Some(value()?),
+            "--audit-log" => audit_log = Some(value()?.into()),
+            "--audit-max-size" => audit_max_size = count(&value()?)? as u64,
+            "--audit-keep" => {
+                let value = value()?;
+                audit_keep = value
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid count {value}"))?;
+            }
+            "--clients" => config.policy = value()?.parse()?,
+            "--ping-interval" => config.heartbeat.interval = seconds(&value()?)?,
+            "--timeout" => config.heartbeat.timeout = seconds(&value()?)?,
+            "--report-interval" => config.report_interval = seconds(&value()?)?,
+            "--jitter-buffer" => config.jitter_buffer = Some(millis(&value()?)?),
+            "--predict" => config.prediction = Some(millis(&value()?)?),
+            "--max-message" => config.limits.max_message = count(&value()?)? as usize,
+            "--max-rate" => config.limits.rate = count(&value()?)?,
+            "--max-fields" => config.limits.max_fields = count(&value()?)? as usize,
+            "--max-errors" => config.limits.max_violations = count(&value()?)?,
+            "--metrics" => config.metrics = Some(value()?),
+            "--log" => log = value()?,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown option {arg}\n\n{USAGE}")),
         }
     }
+    init_logging(&log)?;
+    if let Some(path) = audit_log {
+        config.audit = Some(Arc::new(AuditLog::open(&path, audit_max_size, audit_keep)?));
+    }
+    Ok(config)
 }
 
-fn main() -> Result<(), String> {
-    let ws = WebSocket::new(PenWsFactory {
-        backend: Rc::new(RefCell::new(InputBackend::new()?)),
-    })
-    .err_tostring()?;
-    ws.listen("localhost:57362").err_tostring()?;
-
+// Log lines go to stdout, colored only on a terminal
+fn init_logging(filter: &str) -> Result<(), String> {
+    let filter =
+        EnvFilter::try_new(filter).map_err(|err| format!("invalid log filter {filter}: {err}"))?;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stdout().is_terminal())
+        .init();
     Ok(())
 }
+
+fn run(config: ServerConfig) -> Result<(), String> {
+    // Landlock only restricts threads started after it, so a sandboxed
+    // server keeps to the main thread until the sandbox is in place
+    let mut builder = match config.user {
+        Some(_) => Builder::new_current_thread(),
+        None => Builder::new_multi_thread(),
+    };
+    let runtime = builder
+        .enable_all()
+        .build()
+        .map_err(|err| err.to_string())?;
+    runtime.block_on(serve(config))
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1)).and_then(run);
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
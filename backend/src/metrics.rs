@@ -0,0 +1,270 @@
+//! Process-wide counters, rendered in the Prometheus text format
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// Upper bounds of the latency buckets in microseconds
+const LATENCY_BUCKETS: [u64; 12] = [
+    100, 250, 500, 1000, 2500, 5000, 10000, 25000, 50000, 100000, 250000, 1000000,
+];
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+// Counts per label value, such as messages per type
+#[derive(Default)]
+struct Family(Mutex<BTreeMap<String, u64>>);
+
+impl Family {
+    fn add(&self, label: &str, count: u64) {
+        let mut values = self.0.lock().unwrap();
+        match values.get_mut(label) {
+            Some(value) => *value += count,
+            None => {
+                values.insert(String::from(label), count);
+            }
+        }
+    }
+
+    fn sub(&self, label: &str, count: u64) {
+        if let Some(value) = self.0.lock().unwrap().get_mut(label) {
+            *value = value.saturating_sub(count);
+        }
+    }
+
+    fn render(&self, out: &mut String, name: &str, label: &str) {
+        for (value, count) in self.0.lock().unwrap().iter() {
+            let _ = writeln!(out, "{name}{{{label}=\"{value}\"}} {count}");
+        }
+    }
+}
+
+struct Histogram {
+    // Samples per bucket, not cumulative, the last one past every bound
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&self, micros: u64) {
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut count = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let bound = match LATENCY_BUCKETS.get(index) {
+                Some(bound) => (*bound as f64 / 1e6).to_string(),
+                None => String::from("+Inf"),
+            };
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+        }
+        let sum = self.sum.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+        let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    }
+}
+
+// Samples in the last whole second, counted like the app's useTPS
+struct RateMeter {
+    second: Instant,
+    current: u64,
+    last: u64,
+}
+
+impl RateMeter {
+    // Move on to the second now is in
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.second).as_secs();
+        if elapsed == 0 {
+            return;
+        }
+        self.last = if elapsed == 1 { self.current } else { 0 };
+        self.current = 0;
+        self.second += Duration::from_secs(elapsed);
+    }
+}
+
+// Connected clients per transport, counted while this is alive
+pub struct ActiveConnection(&'static str);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        METRICS.connections.sub(self.0, 1);
+    }
+}
+
+pub struct Metrics {
+    messages: Family,
+    parse_errors: Family,
+    emit_errors: AtomicU64,
+    connections: Family,
+    events: Family,
+    network: Histogram,
+    processing: Histogram,
+    stylus: Mutex<RateMeter>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            messages: Family::default(),
+            parse_errors: Family::default(),
+            emit_errors: AtomicU64::new(0),
+            connections: Family::default(),
+            events: Family::default(),
+            network: Histogram::default(),
+            processing: Histogram::default(),
+            stylus: Mutex::new(RateMeter {
+                second: Instant::now(),
+                current: 0,
+                last: 0,
+            }),
+        }
+    }
+}
+
+impl Metrics {
+    // A message parsed, by its header such as S, F or V
+    pub fn message(&self, key: char) {
+        self.messages.add(key.encode_utf8(&mut [0; 4]), 1);
+    }
+
+    // A message that failed to parse, by the field at fault
+    pub fn parse_error(&self, field: &str) {
+        self.parse_errors.add(field, 1);
+    }
+
+    // The devices refused an action
+    pub fn emit_error(&self) {
+        self.emit_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection(&self, transport: &'static str) -> ActiveConnection {
+        self.connections.add(transport, 1);
+        ActiveConnection(transport)
+    }
+
+    // Input events written to a device, stylus or touchpad
+    pub fn events(&self, device: &str, count: usize) {
+        self.events.add(device, count as u64);
+    }
+
+    // Client timestamp to arrival, in microseconds
+    pub fn network_latency(&self, micros: u64) {
+        self.network.observe(micros);
+    }
+
+    // Arrival to emit, in microseconds
+    pub fn processing_latency(&self, micros: u64) {
+        self.processing.observe(micros);
+    }
+
+    pub fn stylus_samples(&self, count: usize, now: Instant) {
+        let mut meter = self.stylus.lock().unwrap();
+        meter.roll(now);
+        meter.current += count as u64;
+    }
+
+    // Stylus samples emitted in the last whole second
+    pub fn stylus_rate(&self, now: Instant) -> u64 {
+        let mut meter = self.stylus.lock().unwrap();
+        meter.roll(now);
+        meter.last
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "pendroid_messages_total",
+            "counter",
+            "Messages received by type",
+        );
+        self.messages
+            .render(&mut out, "pendroid_messages_total", "type");
+        header(
+            &mut out,
+            "pendroid_parse_errors_total",
+            "counter",
+            "Messages that failed to parse, by field",
+        );
+        self.parse_errors
+            .render(&mut out, "pendroid_parse_errors_total", "field");
+        header(
+            &mut out,
+            "pendroid_emit_errors_total",
+            "counter",
+            "Actions the input devices failed to process",
+        );
+        let emit_errors = self.emit_errors.load(Ordering::Relaxed);
+        let _ = writeln!(out, "pendroid_emit_errors_total {emit_errors}");
+        header(
+            &mut out,
+            "pendroid_connections",
+            "gauge",
+            "Connected clients by transport",
+        );
+        self.connections
+            .render(&mut out, "pendroid_connections", "transport");
+        header(
+            &mut out,
+            "pendroid_events_total",
+            "counter",
+            "Input events emitted by device",
+        );
+        self.events
+            .render(&mut out, "pendroid_events_total", "device");
+        header(
+            &mut out,
+            "pendroid_sample_latency_seconds",
+            "histogram",
+            "Latency of input samples, from the client clock to arrival and from arrival to emit",
+        );
+        self.network.render(
+            &mut out,
+            "pendroid_sample_latency_seconds",
+            "stage=\"network\"",
+        );
+        self.processing.render(
+            &mut out,
+            "pendroid_sample_latency_seconds",
+            "stage=\"processing\"",
+        );
+        header(
+            &mut out,
+            "pendroid_stylus_sample_rate",
+            "gauge",
+            "Stylus samples emitted in the last second",
+        );
+        let rate = self.stylus_rate(Instant::now());
+        let _ = writeln!(out, "pendroid_stylus_sample_rate {rate}");
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
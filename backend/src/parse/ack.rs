@@ -0,0 +1,25 @@
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
+
+// Acknowledges the datagram with this sequence number, so UDP clients know
+// which transitions to send again
+#[derive(Debug, Clone, PartialEq)]
+pub struct AckData {
+    pub sequence: u64,
+}
+
+impl FromSplit for AckData {
+    const KEY: char = 'A';
+    fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        let sequence = split.parse_element::<u64>("sequence")?;
+        Ok(ActionType::Ack(AckData { sequence }))
+    }
+}
+
+impl ToSplit for AckData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.sequence);
+    }
+}
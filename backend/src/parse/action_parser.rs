@@ -1,6 +1,6 @@
-use std::{iter::Peekable, str::Split};
+use std::{fmt::Write, iter::Peekable, str::Split};
 
-use crate::utility::ErrToString;
+use crate::{metrics::METRICS, utility::ErrToString};
 
 pub type ActionElementSplit<'a, 'b> = Split<'a, &'b str>;
 
@@ -17,18 +17,46 @@ pub trait ActionElementSplitParser {
 }
 impl ActionElementSplitParser for ActionElementSplit<'_, '_> {
     fn parse_element<T: ActionElement>(&mut self, name: &'static str) -> Result<T, String> {
-        T::from_element(
-            self.next()
-                .ok_or_else(|| format!("field {name} required"))?,
-        )
+        match self.next() {
+            Some(text) => T::from_element(text),
+            None => Err(format!("field {name} required")),
+        }
+        .inspect_err(|_| METRICS.parse_error(name))
     }
 }
 impl ActionElementSplitParser for Peekable<&mut ActionElementSplit<'_, '_>> {
     fn parse_element<T: ActionElement>(&mut self, name: &'static str) -> Result<T, String> {
-        T::from_element(
-            self.next()
-                .ok_or_else(|| format!("field {name} required"))?,
-        )
+        match self.next() {
+            Some(text) => T::from_element(text),
+            None => Err(format!("field {name} required")),
+        }
+        .inspect_err(|_| METRICS.parse_error(name))
+    }
+}
+
+pub struct ActionElementWriter {
+    text: String,
+    empty: bool,
+}
+impl ActionElementWriter {
+    pub fn new(head: char) -> Self {
+        Self {
+            text: String::from(head),
+            empty: true,
+        }
+    }
+
+    pub fn push_element<T: ActionElement>(&mut self, value: T) {
+        // First element follows the header directly
+        if !self.empty {
+            self.text.push(';');
+        }
+        self.empty = false;
+        value.to_element(&mut self.text);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
     }
 }
 
@@ -37,6 +65,7 @@ where
     Self: Sized,
 {
     fn from_element(text: &str) -> Result<Self, String>;
+    fn to_element(&self, text: &mut String);
 }
 macro_rules! impl_num_action_element {
     ($target:ty) => {
@@ -44,6 +73,10 @@ macro_rules! impl_num_action_element {
             fn from_element(text: &str) -> Result<Self, String> {
                 text.parse::<$target>().err_tostring()
             }
+            fn to_element(&self, text: &mut String) {
+                // Writing into a String never fails
+                let _ = write!(text, "{self}");
+            }
         }
     };
 }
@@ -51,6 +84,10 @@ impl ActionElement for bool {
     fn from_element(text: &str) -> Result<Self, String> {
         Ok(text == "T")
     }
+    fn to_element(&self, text: &mut String) {
+        text.push(if *self { 'T' } else { 'F' });
+    }
 }
 impl_num_action_element!(i32);
 impl_num_action_element!(u32);
+impl_num_action_element!(u64);
@@ -0,0 +1,34 @@
+use super::{
+    action_parser::{ActionElement, ActionElementWriter},
+    ActionElementSplit, ActionElementSplitParser, ActionType, FromSplit, ToSplit,
+};
+
+// Clock offset probe. The server sends its time, the client answers with
+// the same value followed by its own time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockData {
+    pub server_time: u64,
+    pub client_time: Option<u64>,
+}
+
+impl FromSplit for ClockData {
+    const KEY: char = 'C';
+    fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        let server_time = split.parse_element::<u64>("server_time")?;
+        // Only present in the client reply
+        let client_time = split.next().map(u64::from_element).transpose()?;
+        Ok(ActionType::Clock(ClockData {
+            server_time,
+            client_time,
+        }))
+    }
+}
+
+impl ToSplit for ClockData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.server_time);
+        if let Some(client_time) = self.client_time {
+            writer.push_element(client_time);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use super::{
+    action_parser::{ActionElement, ActionElementWriter},
+    ActionElementSplit, ActionElementSplitParser, ActionType, FromSplit, ToSplit,
+};
+
+// Which limit a rejected message broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // Longer than the maximum message size
+    Size,
+    // Sent faster than the allowed message rate
+    Rate,
+    // More fields than any action has
+    Fields,
+}
+
+impl ActionElement for ErrorKind {
+    fn from_element(text: &str) -> Result<Self, String> {
+        match text {
+            "size" => Ok(Self::Size),
+            "rate" => Ok(Self::Rate),
+            "fields" => Ok(Self::Fields),
+            _ => Err(format!("unknown error {text}")),
+        }
+    }
+    fn to_element(&self, text: &mut String) {
+        text.push_str(match self {
+            Self::Size => "size",
+            Self::Rate => "rate",
+            Self::Fields => "fields",
+        });
+    }
+}
+
+// Tells the client a message was dropped, and how many were so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorData {
+    pub kind: ErrorKind,
+    pub count: u64,
+}
+
+impl FromSplit for ErrorData {
+    const KEY: char = 'E';
+    fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        let kind = split.parse_element::<ErrorKind>("kind")?;
+        let count = split.parse_element::<u64>("count")?;
+        Ok(ActionType::Error(ErrorData { kind, count }))
+    }
+}
+
+impl ToSplit for ErrorData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.kind);
+        writer.push_element(self.count);
+    }
+}
@@ -1,6 +1,9 @@
-use super::{ActionElementSplit, ActionElementSplitParser, ActionType, FromSplit};
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Touch {
     pub x: i32,
     pub y: i32,
@@ -18,7 +21,7 @@ impl Default for Touch {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FingerData {
     pub length: i32,
     pub touchs: [Touch; 12],
@@ -45,3 +48,23 @@ impl FromSplit for FingerData {
         Ok(ActionType::Finger(finger_data))
     }
 }
+
+impl ToSplit for FingerData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.length);
+
+        // Trailing default touches are what the parser fills in, so only
+        // touches up to the last non-default one are written
+        let used = self
+            .touchs
+            .iter()
+            .rposition(|touch| *touch != Touch::default())
+            .map_or(0, |index| index + 1);
+        for touch in &self.touchs[..used] {
+            writer.push_element(touch.x);
+            writer.push_element(touch.y);
+            writer.push_element(touch.slot);
+            writer.push_element(touch.tracking_id);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionType, FromSplit, ToSplit,
+};
+
+// Sent by an idle client to show it is still alive, echoed back by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatData;
+
+impl FromSplit for HeartbeatData {
+    const KEY: char = 'H';
+    fn from_split(_split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        Ok(ActionType::Heartbeat(HeartbeatData))
+    }
+}
+
+impl ToSplit for HeartbeatData {
+    fn to_split(&self, _writer: &mut ActionElementWriter) {}
+}
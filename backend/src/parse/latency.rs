@@ -0,0 +1,40 @@
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
+
+// Latency percentiles reported to the client, in microseconds.
+// Network is sample time to receive, processing is receive to emit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyData {
+    pub network: [u32; 3],
+    pub processing: [u32; 3],
+}
+
+impl FromSplit for LatencyData {
+    const KEY: char = 'L';
+    fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        let network = [
+            split.parse_element::<u32>("network_p50")?,
+            split.parse_element::<u32>("network_p95")?,
+            split.parse_element::<u32>("network_p99")?,
+        ];
+        let processing = [
+            split.parse_element::<u32>("processing_p50")?,
+            split.parse_element::<u32>("processing_p95")?,
+            split.parse_element::<u32>("processing_p99")?,
+        ];
+        Ok(ActionType::Latency(LatencyData {
+            network,
+            processing,
+        }))
+    }
+}
+
+impl ToSplit for LatencyData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        for value in self.network.iter().chain(&self.processing) {
+            writer.push_element(*value);
+        }
+    }
+}
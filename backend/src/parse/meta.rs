@@ -0,0 +1,45 @@
+use super::action_parser::ActionElement;
+
+const META_SEPARATOR: char = '|';
+const TIMESTAMP_KEY: char = 't';
+const SEQUENCE_KEY: char = 'n';
+
+// Optional fields after an action, such as ST;T;F;10;20;0;0;100|t1234.
+// Each one is a key character followed by its value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionMeta {
+    // Client clock in microseconds when the sample was taken
+    pub timestamp: Option<u64>,
+    // Increases by one with every message the client sends
+    pub sequence: Option<u64>,
+}
+
+// Separate the action from its metadata. Unknown keys are skipped so older
+// servers keep working with newer clients.
+pub fn split_meta(text: &str) -> Result<(&str, ActionMeta), String> {
+    let mut items = text.split(META_SEPARATOR);
+    let action = items.next().unwrap_or_default();
+    let mut meta = ActionMeta::default();
+    for item in items {
+        let mut chars = item.chars();
+        match chars.next() {
+            Some(TIMESTAMP_KEY) => meta.timestamp = Some(u64::from_element(chars.as_str())?),
+            Some(SEQUENCE_KEY) => meta.sequence = Some(u64::from_element(chars.as_str())?),
+            _ => {}
+        }
+    }
+    Ok((action, meta))
+}
+
+pub fn write_meta(meta: &ActionMeta, text: &mut String) {
+    if let Some(timestamp) = meta.timestamp {
+        text.push(META_SEPARATOR);
+        text.push(TIMESTAMP_KEY);
+        timestamp.to_element(text);
+    }
+    if let Some(sequence) = meta.sequence {
+        text.push(META_SEPARATOR);
+        text.push(SEQUENCE_KEY);
+        sequence.to_element(text);
+    }
+}
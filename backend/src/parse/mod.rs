@@ -1,19 +1,65 @@
+mod ack;
 mod action_parser;
+mod clock;
+mod error;
 mod finger;
+mod heartbeat;
+mod latency;
+mod meta;
 mod stylus;
+mod stylus_batch;
 mod view;
 
+use tracing::trace;
+
+use crate::metrics::METRICS;
+
+use self::meta::{split_meta, write_meta};
 pub use self::{
-    action_parser::{create_action_element_split, ActionElementSplit, ActionElementSplitParser},
-    finger::FingerData,
+    ack::AckData,
+    action_parser::{
+        create_action_element_split, ActionElement, ActionElementSplit, ActionElementSplitParser,
+        ActionElementWriter,
+    },
+    clock::ClockData,
+    error::{ErrorData, ErrorKind},
+    finger::{FingerData, Touch},
+    heartbeat::HeartbeatData,
+    latency::LatencyData,
+    meta::ActionMeta,
     stylus::StylusData,
+    stylus_batch::{StylusBatchData, StylusSample},
     view::ViewData,
 };
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ActionType {
     Stylus(StylusData),
+    StylusBatch(StylusBatchData),
     Screen(ViewData),
     Finger(FingerData),
+    Heartbeat(HeartbeatData),
+    Clock(ClockData),
+    Latency(LatencyData),
+    Ack(AckData),
+    Error(ErrorData),
+}
+
+impl ActionType {
+    // Header character the action is encoded with
+    pub fn key(&self) -> char {
+        match self {
+            ActionType::Stylus(_) => StylusData::KEY,
+            ActionType::StylusBatch(_) => StylusBatchData::KEY,
+            ActionType::Screen(_) => ViewData::KEY,
+            ActionType::Finger(_) => FingerData::KEY,
+            ActionType::Heartbeat(_) => HeartbeatData::KEY,
+            ActionType::Clock(_) => ClockData::KEY,
+            ActionType::Latency(_) => LatencyData::KEY,
+            ActionType::Ack(_) => AckData::KEY,
+            ActionType::Error(_) => ErrorData::KEY,
+        }
+    }
 }
 
 pub trait FromSplit {
@@ -21,20 +67,84 @@ pub trait FromSplit {
     fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String>;
 }
 
+pub trait ToSplit: FromSplit {
+    fn to_split(&self, writer: &mut ActionElementWriter);
+}
+
 pub fn action_parse(text: String) -> Result<ActionType, String> {
-    let (head, mut split) = create_action_element_split(&text)?;
+    action_parse_meta(&text).map(|(action, _meta)| action)
+}
+
+pub fn action_parse_meta(text: &str) -> Result<(ActionType, ActionMeta), String> {
+    let (action, meta) = split_meta(text).map_err(|err| {
+        METRICS.parse_error("meta");
+        format!("{text}: {err}")
+    })?;
+    let (head, mut split) =
+        create_action_element_split(action).inspect_err(|_| METRICS.parse_error("header"))?;
 
     match head {
         // Pen Down Up Out
         StylusData::KEY => StylusData::from_split(&mut split),
 
+        // Historical pen samples of one frame
+        StylusBatchData::KEY => StylusBatchData::from_split(&mut split),
+
         // Gesture End Start Continued
         FingerData::KEY => FingerData::from_split(&mut split),
 
         // View update
         ViewData::KEY => ViewData::from_split(&mut split),
 
-        _ => Err(String::from("Unexpected header")),
+        // Idle keepalive
+        HeartbeatData::KEY => HeartbeatData::from_split(&mut split),
+
+        // Clock offset probe reply
+        ClockData::KEY => ClockData::from_split(&mut split),
+
+        // Latency report
+        LatencyData::KEY => LatencyData::from_split(&mut split),
+
+        // Datagram acknowledgement
+        AckData::KEY => AckData::from_split(&mut split),
+
+        // Rejected message
+        ErrorData::KEY => ErrorData::from_split(&mut split),
+
+        _ => {
+            METRICS.parse_error("header");
+            Err(String::from("Unexpected header"))
+        }
     }
+    .map(|action| {
+        trace!(?action, ?meta, "parsed");
+        (action, meta)
+    })
     .map_err(|err| format!("{text}: {err}"))
 }
+
+fn encode_split<T: ToSplit>(data: &T) -> String {
+    let mut writer = ActionElementWriter::new(T::KEY);
+    data.to_split(&mut writer);
+    writer.finish()
+}
+
+pub fn action_encode(action: &ActionType) -> String {
+    action_encode_meta(action, &ActionMeta::default())
+}
+
+pub fn action_encode_meta(action: &ActionType, meta: &ActionMeta) -> String {
+    let mut text = match action {
+        ActionType::Stylus(stylus_data) => encode_split(stylus_data),
+        ActionType::StylusBatch(batch_data) => encode_split(batch_data),
+        ActionType::Finger(finger_data) => encode_split(finger_data),
+        ActionType::Screen(view_data) => encode_split(view_data),
+        ActionType::Heartbeat(heartbeat) => encode_split(heartbeat),
+        ActionType::Clock(clock_data) => encode_split(clock_data),
+        ActionType::Latency(latency_data) => encode_split(latency_data),
+        ActionType::Ack(ack_data) => encode_split(ack_data),
+        ActionType::Error(error_data) => encode_split(error_data),
+    };
+    write_meta(meta, &mut text);
+    text
+}
@@ -1,6 +1,9 @@
-use super::{ActionElementSplit, ActionElementSplitParser, ActionType, FromSplit};
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StylusData {
     pub x: i32,
     pub y: i32,
@@ -38,3 +41,16 @@ impl FromSplit for StylusData {
         Ok(ActionType::Stylus(stylus_data))
     }
 }
+
+impl ToSplit for StylusData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.hover);
+        writer.push_element(self.down);
+        writer.push_element(self.button);
+        writer.push_element(self.x);
+        writer.push_element(self.y);
+        writer.push_element(self.tilt_x);
+        writer.push_element(self.tilt_y);
+        writer.push_element(self.pressure);
+    }
+}
@@ -0,0 +1,76 @@
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
+
+// Samples preallocated before the count is checked against the fields
+const BATCH_PREALLOCATE: usize = 64;
+
+// One historical sample of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct StylusSample {
+    pub x: i32,
+    pub y: i32,
+    pub tilt_x: i32,
+    pub tilt_y: i32,
+    pub pressure: i32,
+    // Microseconds before the batch timestamp the sample was taken
+    pub age: u32,
+}
+
+// Every sample of one MotionEvent, oldest first. Pen state is shared, as
+// historical samples only move the pen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StylusBatchData {
+    pub down: bool,
+    pub hover: bool,
+    pub button: bool,
+    pub samples: Vec<StylusSample>,
+}
+
+impl FromSplit for StylusBatchData {
+    const KEY: char = 'B';
+    fn from_split(split: &mut ActionElementSplit) -> Result<ActionType, String> {
+        let hover = split.parse_element::<bool>("hover")?;
+        let down = split.parse_element::<bool>("down")?;
+        let button = split.parse_element::<bool>("button")?;
+        let count = split.parse_element::<u32>("count")? as usize;
+
+        let mut samples = Vec::with_capacity(count.min(BATCH_PREALLOCATE));
+        for _ in 0..count {
+            samples.push(StylusSample {
+                x: split.parse_element::<i32>("x")?,
+                y: split.parse_element::<i32>("y")?,
+                tilt_x: split.parse_element::<i32>("tilt_x")?,
+                tilt_y: split.parse_element::<i32>("tilt_y")?,
+                pressure: split.parse_element::<i32>("pressure")?,
+                age: split.parse_element::<u32>("age")?,
+            });
+        }
+
+        let batch_data = StylusBatchData {
+            down,
+            hover,
+            button,
+            samples,
+        };
+        Ok(ActionType::StylusBatch(batch_data))
+    }
+}
+
+impl ToSplit for StylusBatchData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.hover);
+        writer.push_element(self.down);
+        writer.push_element(self.button);
+        writer.push_element(self.samples.len() as u32);
+        for sample in &self.samples {
+            writer.push_element(sample.x);
+            writer.push_element(sample.y);
+            writer.push_element(sample.tilt_x);
+            writer.push_element(sample.tilt_y);
+            writer.push_element(sample.pressure);
+            writer.push_element(sample.age);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use super::{ActionElementSplit, ActionElementSplitParser, ActionType, FromSplit};
+use super::{
+    action_parser::ActionElementWriter, ActionElementSplit, ActionElementSplitParser, ActionType,
+    FromSplit, ToSplit,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ViewData {
     pub width: u32,
     pub height: u32,
@@ -14,3 +17,10 @@ impl FromSplit for ViewData {
         Ok(ActionType::Screen(ViewData { width, height }))
     }
 }
+
+impl ToSplit for ViewData {
+    fn to_split(&self, writer: &mut ActionElementWriter) {
+        writer.push_element(self.width);
+        writer.push_element(self.height);
+    }
+}
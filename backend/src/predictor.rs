@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, time::Duration};
+
+use super::parse::StylusData;
+
+// Samples closer than this carry no usable velocity, usually a network clump
+const MIN_INTERVAL_US: u64 = 1_000;
+// History older than this says nothing about the current motion
+const MAX_INTERVAL_US: u64 = 100_000;
+// Turns sharper than about 45 degrees disable prediction
+const MIN_TURN_COS: f64 = 0.7;
+// Prediction never reaches further than twice the constant velocity guess
+const MAX_ACCELERATION_GAIN: f64 = 2.0;
+
+// Extrapolates the pen position from the velocity and acceleration of the
+// last three samples. Any change of hover, down or button starts over, so
+// nothing is predicted across a pen-down or pen-up.
+pub struct StylusPredictor {
+    lead: f64,
+    // (time in microseconds, x, y), oldest first
+    history: VecDeque<(u64, f64, f64)>,
+    state: Option<(bool, bool, bool)>,
+}
+
+impl StylusPredictor {
+    pub fn new(lead: Duration) -> Self {
+        Self {
+            lead: lead.as_micros() as f64,
+            history: VecDeque::with_capacity(3),
+            state: None,
+        }
+    }
+
+    // Forget the current stroke, such as when the client goes away
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.state = None;
+    }
+
+    // Where to report the pen, the real position when there is no safe guess
+    pub fn predict(&mut self, pen_data: &StylusData, time: u64) -> (i32, i32) {
+        let real = (pen_data.x, pen_data.y);
+        let state = Some((pen_data.hover, pen_data.down, pen_data.button));
+        if self.state != state {
+            self.state = state;
+            self.history.clear();
+        }
+
+        if let Some(&(last, _, _)) = self.history.back() {
+            let interval = time.saturating_sub(last);
+            if interval > MAX_INTERVAL_US || time < last {
+                self.history.clear();
+            } else if interval < MIN_INTERVAL_US {
+                return real;
+            }
+        }
+        if self.history.len() == 3 {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back((time, pen_data.x as f64, pen_data.y as f64));
+
+        self.extrapolate().unwrap_or(real)
+    }
+
+    fn extrapolate(&self) -> Option<(i32, i32)> {
+        let [(t0, x0, y0), (t1, x1, y1), (t2, x2, y2)] = [
+            *self.history.front()?,
+            *self.history.get(1)?,
+            *self.history.get(2)?,
+        ];
+        let dt1 = (t1 - t0) as f64;
+        let dt2 = (t2 - t1) as f64;
+        let velocity0 = ((x1 - x0) / dt1, (y1 - y0) / dt1);
+        let velocity1 = ((x2 - x1) / dt2, (y2 - y1) / dt2);
+
+        let speed0 = velocity0.0.hypot(velocity0.1);
+        let speed1 = velocity1.0.hypot(velocity1.1);
+        if speed0 == 0.0 || speed1 == 0.0 {
+            return None;
+        }
+        let turn = (velocity0.0 * velocity1.0 + velocity0.1 * velocity1.1) / (speed0 * speed1);
+        if turn < MIN_TURN_COS {
+            return None;
+        }
+
+        let span = (dt1 + dt2) / 2.0;
+        let acceleration = (
+            (velocity1.0 - velocity0.0) / span,
+            (velocity1.1 - velocity0.1) / span,
+        );
+        let mut offset = (
+            velocity1.0 * self.lead + acceleration.0 * self.lead * self.lead / 2.0,
+            velocity1.1 * self.lead + acceleration.1 * self.lead * self.lead / 2.0,
+        );
+        let limit = speed1 * self.lead * MAX_ACCELERATION_GAIN;
+        let distance = offset.0.hypot(offset.1);
+        if distance > limit {
+            offset = (offset.0 * limit / distance, offset.1 * limit / distance);
+        }
+        Some((
+            (x2 + offset.0).round() as i32,
+            (y2 + offset.1).round() as i32,
+        ))
+    }
+}
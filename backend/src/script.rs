@@ -0,0 +1,174 @@
+//! Scripted input generators
+//!
+//! Builds sequences of [`ActionType`] for common gestures so tools can drive
+//! a backend without a phone.
+
+use std::f64::consts::TAU;
+
+use super::parse::{ActionType, FingerData, StylusData, Touch};
+
+pub type Point = (i32, i32);
+
+fn lerp(from: f64, to: f64, t: f64) -> f64 {
+    from + (to - from) * t
+}
+
+fn round_point(x: f64, y: f64) -> Point {
+    (x.round() as i32, y.round() as i32)
+}
+
+// Sample steps + 1 points from t = 0 to t = 1
+fn sample(steps: u32, at: impl Fn(f64) -> Point) -> Vec<Point> {
+    let steps = steps.max(1);
+    (0..=steps)
+        .map(|step| at(step as f64 / steps as f64))
+        .collect()
+}
+
+pub fn line(from: Point, to: Point, steps: u32) -> Vec<Point> {
+    sample(steps, |t| {
+        round_point(
+            lerp(from.0 as f64, to.0 as f64, t),
+            lerp(from.1 as f64, to.1 as f64, t),
+        )
+    })
+}
+
+pub fn circle(center: Point, radius: i32, steps: u32) -> Vec<Point> {
+    sample(steps, |t| {
+        round_point(
+            center.0 as f64 + radius as f64 * (t * TAU).cos(),
+            center.1 as f64 + radius as f64 * (t * TAU).sin(),
+        )
+    })
+}
+
+// Cubic bezier curve
+pub fn bezier(points: [Point; 4], steps: u32) -> Vec<Point> {
+    sample(steps, |t| {
+        let u = 1.0 - t;
+        let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+        let (mut x, mut y) = (0.0, 0.0);
+        for (weight, point) in weights.iter().zip(points) {
+            x += weight * point.0 as f64;
+            y += weight * point.1 as f64;
+        }
+        round_point(x, y)
+    })
+}
+
+fn stylus_sample(point: Point, pressure: i32, down: bool, hover: bool) -> ActionType {
+    ActionType::Stylus(StylusData {
+        x: point.0,
+        y: point.1,
+        tilt_x: 0,
+        tilt_y: 0,
+        pressure,
+        down,
+        hover,
+        button: false,
+    })
+}
+
+// Hover in, draw every point with pressure, then lift and hover out
+pub fn stylus_stroke(path: &[Point], pressure: i32) -> Vec<ActionType> {
+    let (Some(&first), Some(&last)) = (path.first(), path.last()) else {
+        return Vec::new();
+    };
+    let mut actions = Vec::with_capacity(path.len() + 3);
+    actions.push(stylus_sample(first, 0, false, true));
+    for &point in path {
+        actions.push(stylus_sample(point, pressure, true, true));
+    }
+    actions.push(stylus_sample(last, 0, false, true));
+    actions.push(stylus_sample(last, 0, false, false));
+    actions
+}
+
+fn finger_frame(points: &[Point], tracking_base: i32, down: bool) -> ActionType {
+    let mut touchs = [Touch::default(); 12];
+    for (slot, (touch, point)) in touchs.iter_mut().zip(points).enumerate() {
+        *touch = if down {
+            Touch {
+                x: point.0,
+                y: point.1,
+                slot: slot as i32,
+                tracking_id: tracking_base + slot as i32,
+            }
+        } else {
+            Touch {
+                x: -1,
+                y: -1,
+                slot: slot as i32,
+                tracking_id: -1,
+            }
+        };
+    }
+    ActionType::Finger(FingerData {
+        length: if down { points.len().min(12) as i32 } else { 0 },
+        touchs,
+    })
+}
+
+// Move several fingers along their own paths, then lift all of them.
+// Every path must have the same length.
+pub fn finger_gesture(paths: &[Vec<Point>], tracking_base: i32) -> Vec<ActionType> {
+    let frames = paths.iter().map(Vec::len).min().unwrap_or(0);
+    if frames == 0 {
+        return Vec::new();
+    }
+    let mut actions = Vec::with_capacity(frames + 1);
+    for frame in 0..frames {
+        let points: Vec<Point> = paths.iter().map(|path| path[frame]).collect();
+        actions.push(finger_frame(&points, tracking_base, true));
+    }
+    let points: Vec<Point> = paths.iter().map(|path| path[frames - 1]).collect();
+    actions.push(finger_frame(&points, tracking_base, false));
+    actions
+}
+
+pub fn tap(at: Point, tracking_base: i32) -> Vec<ActionType> {
+    finger_gesture(&[vec![at]], tracking_base)
+}
+
+// Parallel fingers spaced horizontally, centered on the swipe line
+pub fn swipe(
+    fingers: u32,
+    from: Point,
+    to: Point,
+    spacing: i32,
+    steps: u32,
+    tracking_base: i32,
+) -> Vec<ActionType> {
+    let fingers = fingers.clamp(1, 12) as i32;
+    let paths: Vec<Vec<Point>> = (0..fingers)
+        .map(|finger| {
+            let offset = spacing * finger - spacing * (fingers - 1) / 2;
+            line((from.0 + offset, from.1), (to.0 + offset, to.1), steps)
+        })
+        .collect();
+    finger_gesture(&paths, tracking_base)
+}
+
+// Two fingers on opposite sides of center, moving from one radius to another
+pub fn pinch(
+    center: Point,
+    from_radius: i32,
+    to_radius: i32,
+    steps: u32,
+    tracking_base: i32,
+) -> Vec<ActionType> {
+    let paths = [
+        line(
+            (center.0 - from_radius, center.1),
+            (center.0 - to_radius, center.1),
+            steps,
+        ),
+        line(
+            (center.0 + from_radius, center.1),
+            (center.0 + to_radius, center.1),
+            steps,
+        ),
+    ];
+    finger_gesture(&paths, tracking_base)
+}
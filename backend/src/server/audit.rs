@@ -0,0 +1,196 @@
+use std::{
+    fmt::{self, Write as _},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::error;
+
+use super::{super::utility::ErrToString, stats::SharedStats};
+
+// One connection, from its first packet to its disconnect
+pub struct AuditRecord {
+    // websocket, wss, udp, tcp or unix
+    pub transport: &'static str,
+    pub peer: String,
+    // Sent by browsers and the app's WebSocket handshake
+    pub origin: Option<String>,
+    // Client name and protocol version from ws://host/?label=name&version=1
+    pub label: Option<String>,
+    pub version: Option<String>,
+    // Whether the client got to control the devices
+    pub accepted: bool,
+    // Why the connection was refused or ended
+    pub reason: Option<String>,
+    // Counters of the session once accepted
+    pub stats: Option<SharedStats>,
+    started: SystemTime,
+    start: Instant,
+}
+
+impl AuditRecord {
+    pub fn new(transport: &'static str, peer: String) -> Self {
+        Self {
+            transport,
+            peer,
+            origin: None,
+            label: None,
+            version: None,
+            accepted: false,
+            reason: None,
+            stats: None,
+            started: SystemTime::now(),
+            start: Instant::now(),
+        }
+    }
+
+    // One JSON object, without a newline
+    pub fn to_json(&self) -> String {
+        let started = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let (messages, rejected) = match &self.stats {
+            Some(stats) => {
+                let stats = stats.lock().unwrap();
+                (stats.messages, stats.rejected)
+            }
+            None => (0, 0),
+        };
+        let mut json = String::from("{");
+        let _ = write!(json, "\"started\":{started:.3}");
+        let _ = write!(
+            json,
+            ",\"duration\":{:.3}",
+            self.start.elapsed().as_secs_f64()
+        );
+        let _ = write!(json, ",\"transport\":{}", JsonString(self.transport));
+        let _ = write!(json, ",\"peer\":{}", JsonString(&self.peer));
+        for (key, value) in [
+            ("origin", &self.origin),
+            ("label", &self.label),
+            ("version", &self.version),
+        ] {
+            let _ = match value {
+                Some(value) => write!(json, ",\"{key}\":{}", JsonString(value)),
+                None => write!(json, ",\"{key}\":null"),
+            };
+        }
+        let _ = write!(json, ",\"accepted\":{}", self.accepted);
+        let _ = write!(json, ",\"messages\":{messages},\"rejected\":{rejected}");
+        let reason = self.reason.as_deref().unwrap_or("closed");
+        let _ = write!(json, ",\"reason\":{}}}", JsonString(reason));
+        json
+    }
+}
+
+// Quoted and escaped for JSON
+struct JsonString<'a>(&'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for char in self.0.chars() {
+            match char {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                char if char.is_control() => write!(f, "\\u{:04x}", char as u32)?,
+                char => f.write_char(char)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+struct AuditFile {
+    file: File,
+    size: u64,
+}
+
+// Append-only JSON lines. Once the file would grow past max_size it is
+// renamed to path.1, older ones to path.2 and so on, keeping keep of them.
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    keep: u32,
+    file: Mutex<AuditFile>,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AuditLog({})", self.path.display())
+    }
+}
+
+fn open_append(path: &Path) -> Result<AuditFile, String> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    let size = file.metadata().err_tostring()?.len();
+    Ok(AuditFile { file, size })
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, max_size: u64, keep: u32) -> Result<Self, String> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            file: Mutex::new(open_append(path)?),
+        })
+    }
+
+    // Where rotated files are created
+    pub fn dir(&self) -> PathBuf {
+        match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self, current: &mut AuditFile) -> Result<(), String> {
+        for index in (1..self.keep).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated(index + 1)).err_tostring()?;
+            }
+        }
+        if self.keep == 0 {
+            fs::remove_file(&self.path).err_tostring()?;
+        } else {
+            fs::rename(&self.path, self.rotated(1)).err_tostring()?;
+        }
+        *current = open_append(&self.path)?;
+        Ok(())
+    }
+
+    pub fn write(&self, record: &AuditRecord) {
+        let mut line = record.to_json();
+        line.push('\n');
+        let mut current = self.file.lock().unwrap();
+        if current.size > 0 && current.size + line.len() as u64 > self.max_size {
+            if let Err(err) = self.rotate(&mut current) {
+                error!("audit log rotation failed: {err}");
+            }
+        }
+        match current.file.write_all(line.as_bytes()) {
+            Ok(()) => current.size += line.len() as u64,
+            Err(err) => error!("{}: {err}", self.path.display()),
+        }
+    }
+}
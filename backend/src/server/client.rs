@@ -0,0 +1,262 @@
+use std::time::Instant;
+
+use tracing::{info, warn};
+
+use super::{
+    super::{
+        metrics::METRICS,
+        parse::{action_encode, action_parse_meta, ActionType, ClockData, ErrorData, LatencyData},
+    },
+    clock::{instant_us, now_us, us_instant, ClockSync},
+    jitter::JitterBuffer,
+    limits::Limiter,
+    sequence::{Arrival, Contacts, SequenceTracker},
+    throttle::Throttle,
+    worker::Session,
+    ServerConfig,
+};
+
+// What a connection hands to the backend, in order. Nearly every item is
+// an action, so boxing it would only add an allocation per message.
+#[allow(clippy::large_enum_variant)]
+enum Forward {
+    // An action and its sample time on the server clock
+    Action(ActionType, Option<u64>),
+    // Release everything, a transition may have been lost
+    Resync,
+}
+
+impl Forward {
+    async fn send_to(self, session: &Session, received: Instant) {
+        match self {
+            Forward::Action(action, sample_time) => {
+                session.send(action, received, sample_time).await
+            }
+            Forward::Resync => session.resync().await,
+        }
+    }
+}
+
+// Transport independent handling of one client's messages
+pub struct ClientState {
+    // Shown in logs, the address or another name for the connection
+    peer: String,
+    session: Session,
+    clock: ClockSync,
+    // Input waiting for playout
+    jitter: Option<JitterBuffer<Forward>>,
+    sequence: SequenceTracker,
+    contacts: Contacts,
+    limiter: Limiter,
+    // Malformed messages tend to come in floods
+    warnings: Throttle,
+}
+
+impl ClientState {
+    // With a jitter buffer configured, input is paced by client timestamps
+    pub fn new(peer: String, session: Session, config: &ServerConfig) -> Self {
+        Self {
+            peer,
+            session,
+            clock: ClockSync::default(),
+            jitter: config.jitter_buffer.map(JitterBuffer::new),
+            sequence: SequenceTracker::default(),
+            contacts: Contacts::default(),
+            limiter: Limiter::new(config.limits),
+            warnings: Throttle::default(),
+        }
+    }
+
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    // Log a problem with the client's messages, a few per window
+    pub fn warn(&mut self, message: &str, received: Instant) {
+        match self.warnings.allow(received) {
+            Some(0) => warn!(peer = %self.peer, "{message}"),
+            Some(suppressed) => warn!(peer = %self.peer, suppressed, "{message}"),
+            None => {}
+        }
+    }
+
+    // Whether the client should be dropped for breaking the limits too often.
+    // Dropping the session releases its input.
+    pub fn exceeded_limits(&self) -> bool {
+        self.limiter.exceeded()
+    }
+
+    // Handle one protocol line, returns a reply for the client if any
+    pub async fn receive(&mut self, text: &str, received: Instant) -> Option<String> {
+        self.session.stats().lock().unwrap().messages += 1;
+        if let Err(kind) = self.limiter.check(text, received) {
+            self.session.stats().lock().unwrap().rejected += 1;
+            let count = self.limiter.reject(received);
+            return Some(action_encode(&ActionType::Error(ErrorData { kind, count })));
+        }
+        let (action, meta) = match action_parse_meta(text) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.warn(&err, received);
+                return None;
+            }
+        };
+        METRICS.message(action.key());
+
+        if let Some(sequence) = meta.sequence {
+            if !self.check_sequence(sequence, &action, received).await {
+                return None;
+            }
+        }
+        self.contacts.update(&action);
+
+        let mut reply = None;
+        let mut sample_time = None;
+        match &action {
+            // Answer heartbeats so idle clients can check the server too
+            ActionType::Heartbeat(_) => reply = Some(action_encode(&action)),
+            ActionType::Clock(ClockData {
+                server_time,
+                client_time: Some(client_time),
+            }) => {
+                self.clock
+                    .add_probe(*server_time, *client_time, instant_us(received));
+                return None;
+            }
+            ActionType::Stylus(_) | ActionType::StylusBatch(_) | ActionType::Finger(_) => {
+                sample_time = meta
+                    .timestamp
+                    .and_then(|timestamp| self.clock.to_server(timestamp));
+                if let Some(sent) = sample_time {
+                    let network = instant_us(received).saturating_sub(sent);
+                    self.session.stats().lock().unwrap().network.push(network);
+                    METRICS.network_latency(network);
+                }
+            }
+            _ => {}
+        }
+        self.forward(Forward::Action(action, sample_time), received)
+            .await;
+        reply
+    }
+
+    // Count lost, duplicate and late messages. Returns whether the action
+    // should still be applied.
+    async fn check_sequence(
+        &mut self,
+        sequence: u64,
+        action: &ActionType,
+        received: Instant,
+    ) -> bool {
+        let arrival = self.sequence.arrive(sequence);
+        let is_input = matches!(
+            action,
+            ActionType::Stylus(_) | ActionType::StylusBatch(_) | ActionType::Finger(_)
+        );
+        let resync = matches!(arrival, Arrival::Gap(_)) && !self.contacts.confirmed_by(action);
+        {
+            let mut stats = self.session.stats().lock().unwrap();
+            match arrival {
+                Arrival::InOrder => {}
+                Arrival::Gap(missing) => stats.lost += missing,
+                Arrival::Duplicate => {
+                    stats.duplicates += 1;
+                    return false;
+                }
+                // Later samples already moved the devices on
+                Arrival::Reordered => {
+                    stats.reordered += 1;
+                    if is_input {
+                        return false;
+                    }
+                }
+            }
+            if resync {
+                stats.resyncs += 1;
+            }
+        }
+        if resync {
+            self.forward(Forward::Resync, received).await;
+        }
+        true
+    }
+
+    async fn forward(&mut self, item: Forward, received: Instant) {
+        let Some(jitter) = &mut self.jitter else {
+            return item.send_to(&self.session, received).await;
+        };
+        let sample_time = match &item {
+            Forward::Action(_, sample_time) => *sample_time,
+            Forward::Resync => None,
+        };
+        jitter.push(item, sample_time, instant_us(received));
+    }
+
+    // When buffered input is next due
+    pub fn next_release(&self) -> Option<Instant> {
+        self.jitter.as_ref()?.next_release().map(us_instant)
+    }
+
+    // Forward buffered input that is due. Processing latency is counted from
+    // here, the buffering delay is intended.
+    pub async fn release(&mut self) {
+        let Some(jitter) = &mut self.jitter else {
+            return;
+        };
+        while let Some(item) = jitter.pop_due(now_us()) {
+            item.send_to(&self.session, Instant::now()).await;
+        }
+    }
+
+    // Probe for the clock offset, sent along with every ping
+    pub fn clock_probe(&self) -> String {
+        action_encode(&ActionType::Clock(ClockData {
+            server_time: now_us(),
+            client_time: None,
+        }))
+    }
+
+    // Log the latency percentiles and build the status message for the
+    // client. Network latency is zero until the client sends timestamps.
+    pub fn latency_report(&self) -> Option<String> {
+        let stats = self.session.stats().lock().unwrap();
+        let processing = stats.processing.percentiles()?;
+        let network = stats.network.percentiles();
+        let peer = &self.peer;
+        match network {
+            Some([p50, p95, p99]) => {
+                info!(peer, "network latency p50 {p50}us p95 {p95}us p99 {p99}us")
+            }
+            None => info!(peer, "no client timestamps for network latency"),
+        }
+        let [p50, p95, p99] = processing;
+        info!(
+            peer,
+            "processing latency p50 {p50}us p95 {p95}us p99 {p99}us"
+        );
+        if stats.lost + stats.duplicates + stats.reordered > 0 {
+            info!(
+                peer,
+                lost = stats.lost,
+                duplicates = stats.duplicates,
+                reordered = stats.reordered,
+                resyncs = stats.resyncs,
+                "messages out of sequence"
+            );
+        }
+        if stats.rejected > 0 {
+            info!(
+                peer,
+                rejected = stats.rejected,
+                "messages rejected by limits"
+            );
+        }
+        if stats.coalesced > 0 {
+            info!(peer, coalesced = stats.coalesced, "stale samples coalesced");
+        }
+        Some(action_encode(&ActionType::Latency(LatencyData {
+            network: network.unwrap_or_default(),
+            processing,
+        })))
+    }
+}
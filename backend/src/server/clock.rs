@@ -0,0 +1,62 @@
+use std::{
+    collections::VecDeque,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+// Server clock, microseconds since the process started
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+pub fn now_us() -> u64 {
+    EPOCH.elapsed().as_micros() as u64
+}
+
+pub fn instant_us(instant: Instant) -> u64 {
+    instant.saturating_duration_since(*EPOCH).as_micros() as u64
+}
+
+pub fn us_instant(micros: u64) -> Instant {
+    *EPOCH + Duration::from_micros(micros)
+}
+
+// Probes kept for the offset estimate
+const CLOCK_SAMPLES: usize = 8;
+
+// Estimates client clock minus server clock from C probes, NTP style.
+// The probe with the shortest round trip has the least queueing error.
+#[derive(Default)]
+pub struct ClockSync {
+    // (round trip, offset) in microseconds
+    samples: VecDeque<(u64, i64)>,
+}
+
+impl ClockSync {
+    // sent and received are server times, client is the client time in the reply
+    pub fn add_probe(&mut self, sent: u64, client: u64, received: u64) {
+        let Some(round_trip) = received.checked_sub(sent) else {
+            return;
+        };
+        let midpoint = sent + round_trip / 2;
+        // A client clock this far off is garbage, not a real offset
+        let Ok(offset) = i64::try_from(i128::from(client) - i128::from(midpoint)) else {
+            return;
+        };
+        if self.samples.len() == CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((round_trip, offset));
+    }
+
+    pub fn offset(&self) -> Option<i64> {
+        self.samples
+            .iter()
+            .min_by_key(|(round_trip, _)| *round_trip)
+            .map(|(_, offset)| *offset)
+    }
+
+    // Map a client timestamp onto the server clock, None when it lands
+    // outside of it
+    pub fn to_server(&self, client: u64) -> Option<u64> {
+        u64::try_from(i128::from(client) - i128::from(self.offset()?)).ok()
+    }
+}
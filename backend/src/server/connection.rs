@@ -0,0 +1,222 @@
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    time::sleep_until,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    accept_hdr_async_with_config,
+    tungstenite::{
+        handshake::server::{Callback, ErrorResponse, Request, Response},
+        http::{header::ORIGIN, StatusCode},
+        protocol::{frame::coding::CloseCode, CloseFrame, WebSocketConfig},
+        Message,
+    },
+};
+use tracing::warn;
+
+use super::{
+    super::{metrics::METRICS, utility::ErrToString},
+    audit::AuditRecord,
+    client::ClientState,
+    ticker,
+    worker::WorkerHandle,
+    ServerConfig,
+};
+
+// Messages over this close the connection
+const MAX_WEBSOCKET_MESSAGE: usize = 1 << 20;
+
+// With an acceptor, the client speaks wss://
+pub async fn websocket(
+    stream: TcpStream,
+    peer: SocketAddr,
+    worker: WorkerHandle,
+    config: Arc<ServerConfig>,
+    tls: Option<TlsAcceptor>,
+) {
+    let transport = if tls.is_some() { "wss" } else { "websocket" };
+    let mut record = AuditRecord::new(transport, peer.to_string());
+    let result = match tls {
+        Some(acceptor) => match acceptor.accept(stream).await {
+            Ok(stream) => handle_websocket(stream, peer, worker, &config, &mut record).await,
+            Err(err) => Err(format!("TLS handshake failed: {err}")),
+        },
+        None => handle_websocket(stream, peer, worker, &config, &mut record).await,
+    };
+    if let Err(err) = result {
+        warn!(%peer, "{err}");
+        record.reason.get_or_insert(err);
+    }
+    config.audit(&record);
+}
+
+// Value of key in a query string such as label=alice&x=1
+fn query_value(query: Option<&str>, key: &str) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| String::from(value))
+}
+
+// What the client told us in its handshake request
+struct Handshake<'a> {
+    allowed_origins: &'a [String],
+    origin: Option<String>,
+    // Clients name their devices with ws://host:57362/?label=name, and may
+    // add &version=1 for the audit log
+    label: Option<String>,
+    version: Option<String>,
+    // Set when a web page tried to connect
+    rejected: bool,
+}
+
+impl Callback for &mut Handshake<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        self.label = query_value(request.uri().query(), "label");
+        self.version = query_value(request.uri().query(), "version");
+        // Browsers always send an Origin, so any page could otherwise inject
+        // input through localhost
+        if let Some(origin) = request.headers().get(ORIGIN) {
+            let origin = String::from_utf8_lossy(origin.as_bytes()).into_owned();
+            let allowed = self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&origin));
+            self.origin = Some(origin);
+            if !allowed {
+                self.rejected = true;
+                let mut error = ErrorResponse::new(Some(String::from("origin not allowed")));
+                *error.status_mut() = StatusCode::FORBIDDEN;
+                return Err(error);
+            }
+        }
+        Ok(response)
+    }
+}
+
+async fn handle_websocket<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    peer: SocketAddr,
+    worker: WorkerHandle,
+    config: &ServerConfig,
+    record: &mut AuditRecord,
+) -> Result<(), String> {
+    let mut handshake = Handshake {
+        allowed_origins: &config.allowed_origins,
+        origin: None,
+        label: None,
+        version: None,
+        rejected: false,
+    };
+    // Larger messages are refused without reading them. Smaller ones over
+    // the configured limit are read and answered with an error.
+    let websocket_config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_WEBSOCKET_MESSAGE))
+        .max_frame_size(Some(MAX_WEBSOCKET_MESSAGE));
+    let accept = accept_hdr_async_with_config(stream, &mut handshake, Some(websocket_config));
+    let result = accept.await;
+    record.origin = handshake.origin.clone();
+    record.label = handshake.label.clone();
+    record.version = handshake.version;
+    let mut socket = match result {
+        Ok(socket) => socket,
+        Err(err) => {
+            return Err(match handshake.origin {
+                Some(origin) if handshake.rejected => {
+                    format!("rejected connection from origin {origin}")
+                }
+                _ => err.to_string(),
+            })
+        }
+    };
+
+    let session = match worker.connect(handshake.label).await {
+        Ok(session) => session,
+        Err(err) => {
+            let _ = socket
+                .close(Some(CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: err.as_str().into(),
+                }))
+                .await;
+            return Err(err);
+        }
+    };
+
+    record.accepted = true;
+    record.stats = Some(session.stats().clone());
+    let _active = METRICS.connection(record.transport);
+    let mut client = ClientState::new(peer.to_string(), session, config);
+
+    // Any frame from the client, including pongs, counts as a sign of life
+    let mut last_seen = Instant::now();
+    let mut ping = ticker(config.heartbeat.interval);
+    let mut report = ticker(config.report_interval);
+
+    loop {
+        let release = client.next_release();
+        tokio::select! {
+            reason = client.session().kicked() => {
+                let _ = socket
+                    .close(Some(CloseFrame {
+                        code: CloseCode::Policy,
+                        reason: reason.into(),
+                    }))
+                    .await;
+                record.reason = Some(String::from(reason));
+                return Ok(());
+            }
+            _ = ping.tick() => {
+                if last_seen.elapsed() >= config.heartbeat.timeout {
+                    let _ = socket
+                        .close(Some(CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "heartbeat timeout".into(),
+                        }))
+                        .await;
+                    return Err(String::from("heartbeat timeout"));
+                }
+                socket.send(Message::Ping(Default::default())).await.err_tostring()?;
+                socket.send(Message::text(client.clock_probe())).await.err_tostring()?;
+            }
+            _ = sleep_until(release.unwrap_or_else(Instant::now).into()), if release.is_some() => {
+                client.release().await;
+            }
+            _ = report.tick() => {
+                if let Some(status) = client.latency_report() {
+                    socket.send(Message::text(status)).await.err_tostring()?;
+                }
+            }
+            message = socket.next() => {
+                let received = Instant::now();
+                last_seen = received;
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Some(reply) = client.receive(&text, received).await {
+                            socket.send(Message::text(reply)).await.err_tostring()?;
+                        }
+                        if client.exceeded_limits() {
+                            let _ = socket
+                                .close(Some(CloseFrame {
+                                    code: CloseCode::Policy,
+                                    reason: "too many rejected messages".into(),
+                                }))
+                                .await;
+                            return Err(String::from("too many rejected messages"));
+                        }
+                    }
+                    // Pings are answered by tungstenite itself
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => client.warn("unexpected binary message", received),
+                    Some(Err(err)) => return Err(err.to_string()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::VecDeque, time::Duration};
+
+// Upper bound for the adaptive delay, past this the pen feels detached
+const MAX_DELAY_US: u64 = 200_000;
+// Smoothing of the transit and jitter estimates, RFC 3550 uses 1/16
+const SMOOTHING: f64 = 1.0 / 16.0;
+// Delay in units of jitter needed to cover most late samples
+const JITTER_MARGIN: f64 = 3.0;
+// A transit change this large is a clock jump, not network jitter
+const RESET_THRESHOLD_US: f64 = 1_000_000.0;
+// Longest backlog, past this everything queued is released at once
+const MAX_QUEUE: usize = 1024;
+
+// Playout buffer that releases samples paced by their client timestamps
+// instead of by when the network delivered them. Times are server clock
+// microseconds. Items leave in the order they came in.
+pub struct JitterBuffer<T> {
+    min_delay: u64,
+    // Smoothed receive minus sample time
+    transit: Option<f64>,
+    last_transit: Option<f64>,
+    // Smoothed change in transit between samples
+    jitter: f64,
+    // (release time, item)
+    queue: VecDeque<(u64, T)>,
+}
+
+impl<T> JitterBuffer<T> {
+    pub fn new(min_delay: Duration) -> Self {
+        Self {
+            min_delay: (min_delay.as_micros() as u64).min(MAX_DELAY_US),
+            transit: None,
+            last_transit: None,
+            jitter: 0.0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    // Extra delay on top of the usual transit time
+    pub fn delay(&self) -> u64 {
+        ((self.jitter * JITTER_MARGIN) as u64).clamp(self.min_delay, MAX_DELAY_US)
+    }
+
+    // Items without a sample time are released right after the ones before
+    pub fn push(&mut self, item: T, sample_time: Option<u64>, received: u64) {
+        let after = self.queue.back().map_or(received, |(release, _)| *release);
+        let release = match sample_time {
+            Some(sample_time) => {
+                let transit = received as f64 - sample_time as f64;
+                // Start the estimate over when the client clock jumped
+                if self
+                    .transit
+                    .is_some_and(|smoothed| (transit - smoothed).abs() > RESET_THRESHOLD_US)
+                {
+                    self.transit = None;
+                    self.last_transit = None;
+                    self.jitter = 0.0;
+                }
+                if let Some(last) = self.last_transit {
+                    self.jitter += ((transit - last).abs() - self.jitter) * SMOOTHING;
+                }
+                self.last_transit = Some(transit);
+                let smoothed = match self.transit {
+                    Some(smoothed) => smoothed + (transit - smoothed) * SMOOTHING,
+                    None => transit,
+                };
+                self.transit = Some(smoothed);
+                let release = sample_time as f64 + smoothed + self.delay() as f64;
+                (release.max(0.0) as u64)
+                    .min(received.saturating_add(MAX_DELAY_US))
+                    .max(after)
+            }
+            None => after,
+        };
+        if self.queue.len() >= MAX_QUEUE {
+            for (queued, _) in &mut self.queue {
+                *queued = (*queued).min(received);
+            }
+        }
+        self.queue.push_back((release, item));
+    }
+
+    // When the oldest item is due
+    pub fn next_release(&self) -> Option<u64> {
+        self.queue.front().map(|(release, _)| *release)
+    }
+
+    pub fn pop_due(&mut self, now: u64) -> Option<T> {
+        if self.next_release()? > now {
+            return None;
+        }
+        self.queue.pop_front().map(|(_, item)| item)
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use super::super::parse::ErrorKind;
+
+// Window over which rejected messages are counted towards a disconnect
+const VIOLATION_WINDOW: Duration = Duration::from_secs(1);
+
+// What one connection may send
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // Longest message in bytes, metadata included
+    pub max_message: usize,
+    // Sustained messages per second, bursts of up to a second are allowed
+    pub rate: u32,
+    // Most ; separated fields in a message. A batch of 64 samples has 388.
+    pub max_fields: usize,
+    // Rejected messages within a second after which the client is dropped
+    pub max_violations: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_message: 4096,
+            rate: 1000,
+            max_fields: 512,
+            max_violations: 100,
+        }
+    }
+}
+
+// Refills rate tokens per second up to rate, one taken per message
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.refilled = self.refilled.max(now);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+// Checks the messages of one connection against its limits
+pub struct Limiter {
+    limits: Limits,
+    bucket: TokenBucket,
+    // Rejected messages so far, reported back to the client
+    count: u64,
+    window_start: Instant,
+    window_count: u32,
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            bucket: TokenBucket::new(limits.rate),
+            count: 0,
+            window_start: Instant::now(),
+            window_count: 0,
+        }
+    }
+
+    // Which limit text breaks, checked before it is parsed
+    pub fn check(&mut self, text: &str, received: Instant) -> Result<(), ErrorKind> {
+        if !self.bucket.take(received) {
+            return Err(ErrorKind::Rate);
+        }
+        if text.len() > self.limits.max_message {
+            return Err(ErrorKind::Size);
+        }
+        if text.bytes().filter(|byte| *byte == b';').count() >= self.limits.max_fields {
+            return Err(ErrorKind::Fields);
+        }
+        Ok(())
+    }
+
+    // Count a rejected message, returns the count so far
+    pub fn reject(&mut self, received: Instant) -> u64 {
+        if received.saturating_duration_since(self.window_start) >= VIOLATION_WINDOW {
+            self.window_start = received;
+            self.window_count = 0;
+        }
+        self.window_count += 1;
+        self.count += 1;
+        self.count
+    }
+
+    // Whether the client broke the limits too often to keep serving it
+    pub fn exceeded(&self) -> bool {
+        self.window_count > self.limits.max_violations
+    }
+}
@@ -0,0 +1,196 @@
+use std::{sync::Arc, time::Instant};
+
+use tokio::{
+    io::{split, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, UnixListener},
+    time::sleep_until,
+};
+use tracing::warn;
+
+use super::{
+    super::{metrics::METRICS, utility::ErrToString},
+    audit::AuditRecord,
+    client::ClientState,
+    ticker,
+    worker::WorkerHandle,
+    ServerConfig,
+};
+
+// Serve newline-delimited protocol lines over plain TCP
+pub async fn serve_tcp_lines(
+    listener: TcpListener,
+    worker: WorkerHandle,
+    config: Arc<ServerConfig>,
+) -> Result<(), String> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(connection(
+                    stream,
+                    "tcp",
+                    peer.to_string(),
+                    worker.clone(),
+                    config.clone(),
+                ));
+            }
+            Err(err) => warn!("accept failed: {err}"),
+        }
+    }
+}
+
+// Serve newline-delimited protocol lines on a Unix socket. Unix clients are
+// usually unnamed, so they are numbered in the logs.
+pub async fn serve_unix_lines(
+    listener: UnixListener,
+    worker: WorkerHandle,
+    config: Arc<ServerConfig>,
+) -> Result<(), String> {
+    let mut count = 0u64;
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                count += 1;
+                tokio::spawn(connection(
+                    stream,
+                    "unix",
+                    format!("unix#{count}"),
+                    worker.clone(),
+                    config.clone(),
+                ));
+            }
+            Err(err) => warn!("accept failed: {err}"),
+        }
+    }
+}
+
+async fn connection<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    transport: &'static str,
+    peer: String,
+    worker: WorkerHandle,
+    config: Arc<ServerConfig>,
+) {
+    let mut record = AuditRecord::new(transport, peer.clone());
+    if let Err(err) = handle_lines(stream, &peer, worker, &config, &mut record).await {
+        warn!(peer, "{err}");
+        record.reason.get_or_insert(err);
+    }
+    config.audit(&record);
+}
+
+// Reads lines without holding more than max bytes of one in memory. Lines
+// over max are skipped and returned as a prefix of max + 1 bytes, so they
+// still break the size limit. Partial lines are kept in the reader, so next
+// can be cancelled in select!.
+struct LineReader<R> {
+    reader: R,
+    max: usize,
+    line: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> LineReader<R> {
+    fn new(reader: R, max: usize) -> Self {
+        Self {
+            reader,
+            max,
+            line: Vec::new(),
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<String>, String> {
+        loop {
+            let available = self.reader.fill_buf().await.err_tostring()?;
+            // A last line without a newline still counts
+            if available.is_empty() {
+                return Ok((!self.line.is_empty()).then(|| self.take_line()));
+            }
+            let end = available.iter().position(|byte| *byte == b'\n');
+            let chunk = &available[..end.unwrap_or(available.len())];
+            let room = (self.max + 1).saturating_sub(self.line.len());
+            self.line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            let consumed = end.map_or(available.len(), |end| end + 1);
+            self.reader.consume(consumed);
+            if end.is_some() {
+                return Ok(Some(self.take_line()));
+            }
+        }
+    }
+
+    // The cut off prefix of a long line may end inside a character
+    fn take_line(&mut self) -> String {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        line
+    }
+}
+
+async fn write_line(
+    writer: &mut (impl AsyncWrite + Unpin),
+    mut text: String,
+) -> Result<(), String> {
+    text.push('\n');
+    writer.write_all(text.as_bytes()).await.err_tostring()
+}
+
+// Same lifecycle as a WebSocket client. Without protocol pings, any line
+// counts as a sign of life, idle clients send H.
+async fn handle_lines<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    peer: &str,
+    worker: WorkerHandle,
+    config: &ServerConfig,
+    record: &mut AuditRecord,
+) -> Result<(), String> {
+    let (reader, mut writer) = split(stream);
+    let mut lines = LineReader::new(BufReader::new(reader), config.limits.max_message);
+    let session = worker.connect(None).await?;
+    record.accepted = true;
+    record.stats = Some(session.stats().clone());
+    let _active = METRICS.connection(record.transport);
+    let mut client = ClientState::new(String::from(peer), session, config);
+
+    let mut last_seen = Instant::now();
+    let mut ping = ticker(config.heartbeat.interval);
+    let mut report = ticker(config.report_interval);
+
+    loop {
+        let release = client.next_release();
+        tokio::select! {
+            reason = client.session().kicked() => {
+                record.reason = Some(String::from(reason));
+                return Ok(());
+            }
+            _ = ping.tick() => {
+                if last_seen.elapsed() >= config.heartbeat.timeout {
+                    return Err(String::from("heartbeat timeout"));
+                }
+                write_line(&mut writer, client.clock_probe()).await?;
+            }
+            _ = sleep_until(release.unwrap_or_else(Instant::now).into()), if release.is_some() => {
+                client.release().await;
+            }
+            _ = report.tick() => {
+                if let Some(status) = client.latency_report() {
+                    write_line(&mut writer, status).await?;
+                }
+            }
+            line = lines.next() => {
+                let received = Instant::now();
+                last_seen = received;
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                let line = line.trim_end_matches('\r');
+                if line.is_empty() {
+                    continue;
+                }
+                if let Some(reply) = client.receive(line, received).await {
+                    write_line(&mut writer, reply).await?;
+                }
+                if client.exceeded_limits() {
+                    return Err(String::from("too many rejected messages"));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tracing::info;
+
+use super::super::utility::ErrToString;
+
+pub const SERVICE_TYPE: &str = "_pendroid._tcp.local.";
+// Bumped when the app and the backend stop understanding each other
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Machine name shown in the app, falls back to pendroid
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| String::from(name.trim()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("pendroid"))
+}
+
+// What the app learns about a host before connecting
+#[derive(Debug, Clone)]
+pub struct ServiceDetails {
+    pub hostname: String,
+    pub port: u16,
+    // Whether the app has to pair before input is accepted
+    pub pairing: bool,
+    // Certificate to pin when the host serves wss://
+    pub tls_fingerprint: Option<String>,
+}
+
+impl ServiceDetails {
+    pub fn txt_records(&self) -> Vec<(&'static str, String)> {
+        let flag = |on: bool| String::from(if on { "1" } else { "0" });
+        let mut records = vec![
+            ("version", PROTOCOL_VERSION.to_string()),
+            ("hostname", self.hostname.clone()),
+            ("pairing", flag(self.pairing)),
+            ("tls", flag(self.tls_fingerprint.is_some())),
+        ];
+        if let Some(fingerprint) = &self.tls_fingerprint {
+            records.push(("fingerprint", fingerprint.clone()));
+        }
+        records
+    }
+}
+
+// Announces the backend over DNS-SD until dropped
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    pub fn start(details: &ServiceDetails) -> Result<Self, String> {
+        let daemon = ServiceDaemon::new().err_tostring()?;
+        let host = format!("{}.local.", details.hostname);
+        let records = details.txt_records();
+        let records: Vec<(&str, &str)> = records
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect();
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            &details.hostname,
+            &host,
+            "",
+            details.port,
+            records.as_slice(),
+        )
+        .err_tostring()?
+        .enable_addr_auto();
+        let fullname = String::from(service.get_fullname());
+        daemon.register(service).err_tostring()?;
+        info!("Advertising {fullname}");
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{debug, warn};
+
+use super::super::{metrics::METRICS, utility::ErrToString};
+
+// Longest request head read, a scrape needs far less
+const MAX_REQUEST: u64 = 8192;
+// Scrapers that do not finish their request by then are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Answer GET /metrics in the Prometheus text format. Anything else is a 404,
+// one request per connection.
+pub async fn serve_metrics(listener: TcpListener) -> Result<(), String> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(async move {
+                    if let Err(err) = scrape(stream).await {
+                        debug!(%peer, "metrics: {err}");
+                    }
+                });
+            }
+            Err(err) => warn!("accept failed: {err}"),
+        }
+    }
+}
+
+async fn scrape(stream: TcpStream) -> Result<(), String> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST));
+    let path = timeout(REQUEST_TIMEOUT, read_request(&mut reader))
+        .await
+        .map_err(|_| String::from("request timed out"))??;
+
+    let (status, body) = match path.as_deref() {
+        Some("/metrics") => ("200 OK", METRICS.render()),
+        Some(_) => ("404 Not Found", String::from("not found\n")),
+        None => ("405 Method Not Allowed", String::from("only GET\n")),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).await.err_tostring()?;
+    writer.shutdown().await.err_tostring()
+}
+
+// Path of a GET request without its query, None for other methods. The
+// headers are read and ignored.
+async fn read_request(
+    reader: &mut (impl AsyncBufReadExt + Unpin),
+) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.err_tostring()?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(String::from("malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or_default();
+    let path = (method == "GET").then(|| String::from(path));
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await.err_tostring()? == 0 {
+            return Err(String::from("request ended before its headers"));
+        }
+        if line.trim_end().is_empty() {
+            return Ok(path);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! WebSocket server feeding an [`InputBackend`](super::backend::InputBackend)
+
+mod audit;
+mod client;
+mod clock;
+mod connection;
+mod jitter;
+mod limits;
+mod lines;
+mod mdns;
+mod metrics;
+mod sandbox;
+mod sequence;
+mod stats;
+mod systemd;
+mod throttle;
+mod tls;
+mod udp;
+mod worker;
+
+use std::{
+    fs,
+    future::Future,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    net::{TcpListener, UdpSocket, UnixListener},
+    signal::unix::{signal, Signal, SignalKind},
+    time::{interval_at, Interval, MissedTickBehavior},
+};
+use tracing::{error, info, warn};
+
+pub use audit::{AuditLog, AuditRecord};
+pub use clock::ClockSync;
+pub use jitter::JitterBuffer;
+pub use limits::Limits;
+pub use lines::{serve_tcp_lines, serve_unix_lines};
+pub use mdns::{hostname, Advertisement, ServiceDetails, PROTOCOL_VERSION, SERVICE_TYPE};
+pub use metrics::serve_metrics;
+pub use sandbox::{restrict, syscall_filter, Account};
+pub use sequence::{Arrival, Contacts, SequenceTracker};
+pub use stats::{LatencyWindow, SessionStats, SharedStats};
+pub use systemd::{watchdog_interval, ActivatedSockets, Notifier};
+pub use throttle::Throttle;
+pub use tls::{default_tls_dir, fingerprint, TlsIdentity};
+pub use udp::serve_udp;
+pub use worker::{ClientPolicy, CreateDevices, Devices, PendingWorker, Session, WorkerHandle};
+
+use tokio_rustls::TlsAcceptor;
+
+use super::utility::ErrToString;
+
+pub const DEFAULT_LISTEN: &str = "localhost:57362";
+
+// Liveness checks for connected clients
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    // How often the server pings each client
+    pub interval: Duration,
+    // Silence after which a client is considered dead and disconnected
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen: String,
+    // Also accept datagrams on this address
+    pub udp: Option<String>,
+    // Also accept newline-delimited lines over plain TCP and a Unix socket
+    pub tcp: Option<String>,
+    pub unix: Option<PathBuf>,
+    // Serve wss:// with a self-signed certificate kept in tls_dir, or in
+    // the default config directory
+    pub tls: bool,
+    pub tls_dir: Option<PathBuf>,
+    // Advertise the WebSocket listener over DNS-SD
+    pub mdns: bool,
+    // Browser origins allowed to connect, such as https://example.com.
+    // Clients without an Origin header are not browsers and always allowed.
+    pub allowed_origins: Vec<String>,
+    pub policy: ClientPolicy,
+    pub heartbeat: Heartbeat,
+    // How often latency percentiles are logged and sent to the client
+    pub report_interval: Duration,
+    // Minimum playout delay of the jitter buffer, None sends input as it arrives
+    pub jitter_buffer: Option<Duration>,
+    // How far ahead the stylus is predicted, None reports it as received
+    pub prediction: Option<Duration>,
+    pub limits: Limits,
+    // Switch to this user, and group if given, once the devices exist and
+    // sandbox the process. Requires shared devices.
+    pub user: Option<String>,
+    pub group: Option<String>,
+    // Where every connection is recorded when it ends
+    pub audit: Option<Arc<AuditLog>>,
+    // Serve Prometheus metrics over HTTP on this address
+    pub metrics: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: String::from(DEFAULT_LISTEN),
+            udp: None,
+            tcp: None,
+            unix: None,
+            tls: false,
+            tls_dir: None,
+            mdns: false,
+            allowed_origins: Vec::new(),
+            policy: ClientPolicy::Takeover,
+            heartbeat: Heartbeat::default(),
+            report_interval: Duration::from_secs(10),
+            jitter_buffer: None,
+            prediction: None,
+            limits: Limits::default(),
+            user: None,
+            group: None,
+            audit: None,
+            metrics: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    fn audit(&self, record: &AuditRecord) {
+        if let Some(log) = &self.audit {
+            log.write(record);
+        }
+    }
+}
+
+// SIGINT and SIGTERM, both shut the server down
+struct Signals {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+impl Signals {
+    fn new() -> Result<Self, String> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt()).err_tostring()?,
+            terminate: signal(SignalKind::terminate()).err_tostring()?,
+        })
+    }
+
+    // Resolves with the name of the next signal
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "Interrupted",
+            _ = self.terminate.recv() => "Terminated",
+        }
+    }
+}
+
+// Ticks every period, starting one period from now
+fn ticker(period: Duration) -> Interval {
+    let mut interval = interval_at((Instant::now() + period).into(), period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
+
+async fn log_error(task: impl Future<Output = Result<(), String>>) {
+    if let Err(err) = task.await {
+        error!("{err}");
+    }
+}
+
+// Binding with std resolves the address on this thread, tokio would start
+// a blocking thread for it before the sandbox is in place
+fn bind_tcp(address: &str) -> Result<TcpListener, String> {
+    let listener = std::net::TcpListener::bind(address).err_tostring()?;
+    listener.set_nonblocking(true).err_tostring()?;
+    TcpListener::from_std(listener).err_tostring()
+}
+
+fn bind_udp(address: &str) -> Result<UdpSocket, String> {
+    let socket = std::net::UdpSocket::bind(address).err_tostring()?;
+    socket.set_nonblocking(true).err_tostring()?;
+    UdpSocket::from_std(socket).err_tostring()
+}
+
+// A socket file left behind by an earlier run is replaced, anything else
+// at the path is an error
+fn bind_unix(path: &Path) -> Result<UnixListener, String> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path).err_tostring()?;
+    }
+    UnixListener::bind(path).err_tostring()
+}
+
+// Sockets passed by systemd socket activation are used instead of binding.
+// They are told apart by FileDescriptorName=: udp, tcp, unix and metrics
+// for the extra listeners, any other name for the WebSocket listener.
+pub async fn serve(config: ServerConfig) -> Result<(), String> {
+    // Devices made while serving would need the privileges given up
+    let account = match &config.user {
+        Some(_) if config.policy == ClientPolicy::PerClient => {
+            return Err(String::from(
+                "--user needs shared devices, per-client devices are created while serving",
+            ))
+        }
+        Some(user) => Some(Account::lookup(user, config.group.as_deref())?),
+        None => None,
+    };
+    let mut activated = ActivatedSockets::from_env()?;
+    let notifier = Notifier::from_env()?.map(Arc::new);
+    let mut signals = Signals::new()?;
+
+    // Everything needing privileges or files is set up before the sandbox
+    let udp = match (activated.take_udp("udp")?, &config.udp) {
+        (Some(socket), _) => Some(socket),
+        (None, Some(address)) => Some(bind_udp(address)?),
+        (None, None) => None,
+    };
+    let tcp = match (activated.take_tcp("tcp")?, &config.tcp) {
+        (Some(listener), _) => Some(listener),
+        (None, Some(address)) => Some(bind_tcp(address)?),
+        (None, None) => None,
+    };
+    // Only a socket file this process created is removed on exit
+    let mut unix_path = None;
+    let unix = match (activated.take_unix("unix")?, &config.unix) {
+        (Some(listener), _) => Some(listener),
+        (None, Some(path)) => {
+            unix_path = Some(path.clone());
+            Some(bind_unix(path)?)
+        }
+        (None, None) => None,
+    };
+    let metrics = match (activated.take_tcp("metrics")?, &config.metrics) {
+        (Some(listener), _) => Some(listener),
+        (None, Some(address)) => Some(bind_tcp(address)?),
+        (None, None) => None,
+    };
+    let listener = match activated.take_any() {
+        Some(fd) => systemd::tcp_listener(fd)?,
+        None => bind_tcp(&config.listen)?,
+    };
+    let identity = if config.tls {
+        let dir = match &config.tls_dir {
+            Some(dir) => dir.clone(),
+            None => default_tls_dir()?,
+        };
+        let identity = TlsIdentity::load_or_create(&dir)?;
+        info!(
+            "TLS certificate SHA-256 fingerprint {}",
+            identity.fingerprint
+        );
+        Some(identity)
+    } else {
+        None
+    };
+    let service = ServiceDetails {
+        hostname: hostname(),
+        port: listener.local_addr().err_tostring()?.port(),
+        pairing: false,
+        tls_fingerprint: identity
+            .as_ref()
+            .map(|identity| identity.fingerprint.clone()),
+    };
+    let pending = WorkerHandle::prepare(config.policy, config.prediction)?;
+
+    if let Some(account) = account {
+        account.switch()?;
+        // Rotating the audit log needs its directory
+        let writable: Vec<PathBuf> = config.audit.iter().map(|log| log.dir()).collect();
+        sandbox::restrict(&writable)?;
+        info!(
+            "Running as uid {} gid {}, sandboxed",
+            account.uid, account.gid
+        );
+    }
+    let worker = pending.start()?;
+
+    let shared = Arc::new(config.clone());
+    // Extra listeners, stopped on shutdown
+    let mut tasks = Vec::new();
+    if let Some(socket) = udp {
+        tasks.push(tokio::spawn(log_error(serve_udp(
+            socket,
+            worker.clone(),
+            shared.clone(),
+        ))));
+    }
+    if let Some(listener) = tcp {
+        tasks.push(tokio::spawn(log_error(serve_tcp_lines(
+            listener,
+            worker.clone(),
+            shared.clone(),
+        ))));
+    }
+    if let Some(listener) = unix {
+        tasks.push(tokio::spawn(log_error(serve_unix_lines(
+            listener,
+            worker.clone(),
+            shared.clone(),
+        ))));
+    }
+    if let Some(listener) = metrics {
+        info!(
+            "Metrics at http://{}/metrics",
+            listener.local_addr().err_tostring()?
+        );
+        tasks.push(tokio::spawn(log_error(serve_metrics(listener))));
+    }
+
+    // Kept alive while serving, dropping it withdraws the service
+    let _advertisement = if config.mdns {
+        Some(Advertisement::start(&service)?)
+    } else {
+        None
+    };
+
+    if let Some(notifier) = &notifier {
+        notifier.notify("READY=1")?;
+        if let Some(period) = watchdog_interval() {
+            tokio::spawn(systemd::watchdog(notifier.clone(), period, worker.clone()));
+        }
+    }
+
+    let serving = async {
+        match identity {
+            Some(identity) => serve_tls_listener(listener, worker.clone(), config, identity).await,
+            None => serve_listener(listener, worker.clone(), config).await,
+        }
+    };
+    let result = tokio::select! {
+        result = serving => result,
+        name = signals.recv() => {
+            info!("{name}, releasing input. Again to exit immediately");
+            Ok(())
+        }
+    };
+
+    // Stop taking connections and messages
+    for task in tasks {
+        task.abort();
+    }
+    if let Some(Err(err)) = notifier.map(|notifier| notifier.notify("STOPPING=1")) {
+        warn!("{err}");
+    }
+    // Pressed buttons are released before the devices disappear. A stuck
+    // device would hold this up, so a second signal gives up on it.
+    tokio::select! {
+        _ = worker.shutdown() => {}
+        name = signals.recv() => {
+            warn!("{name} again, exiting without releasing input");
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = unix_path {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+// Accept WebSocket clients on an already bound listener
+pub async fn serve_listener(
+    listener: TcpListener,
+    worker: WorkerHandle,
+    config: ServerConfig,
+) -> Result<(), String> {
+    accept_websockets(listener, worker, config, None).await
+}
+
+// Same as serve_listener, for wss:// clients
+pub async fn serve_tls_listener(
+    listener: TcpListener,
+    worker: WorkerHandle,
+    config: ServerConfig,
+    identity: TlsIdentity,
+) -> Result<(), String> {
+    accept_websockets(listener, worker, config, Some(identity.acceptor)).await
+}
+
+async fn accept_websockets(
+    listener: TcpListener,
+    worker: WorkerHandle,
+    config: ServerConfig,
+    tls: Option<TlsAcceptor>,
+) -> Result<(), String> {
+    let config = Arc::new(config);
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(connection::websocket(
+                    stream,
+                    peer,
+                    worker.clone(),
+                    config.clone(),
+                    tls.clone(),
+                ));
+            }
+            Err(err) => warn!("accept failed: {err}"),
+        }
+    }
+}
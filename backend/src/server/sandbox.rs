@@ -0,0 +1,234 @@
+use std::{collections::BTreeMap, ffi::CString, io, mem::MaybeUninit, path::PathBuf, ptr};
+
+use landlock::{
+    path_beneath_rules, Access, AccessFs, AccessNet, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetStatus, Scope, ABI,
+};
+use seccompiler::{apply_filter_all_threads, BpfProgram, SeccompAction, SeccompFilter};
+use tracing::warn;
+
+use super::super::utility::ErrToString;
+
+// Newest Landlock version used, older kernels enforce what they know
+const LANDLOCK_ABI: ABI = ABI::V6;
+
+// Buffer for the strings of a passwd or group entry
+const ENTRY_BUFFER: usize = 16384;
+
+// Syscalls left after the sandbox: the async runtime, sockets, threads and
+// writes to the already open uinput devices
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_close,
+    libc::SYS_ioctl,
+    libc::SYS_fcntl,
+    libc::SYS_lseek,
+    libc::SYS_statx,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_futex,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_prctl,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_tgkill,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_getrandom,
+    libc::SYS_eventfd2,
+    libc::SYS_pipe2,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_ppoll,
+    libc::SYS_socket,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept4,
+    libc::SYS_connect,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_shutdown,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+];
+
+// Added when some directories stay writable, to create and rotate files
+const FILE_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_openat,
+    libc::SYS_renameat,
+    libc::SYS_renameat2,
+    libc::SYS_unlinkat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rename,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+];
+
+// Account the server switches to once the devices exist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Account {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+impl Account {
+    // The user's primary group is used unless group is given
+    pub fn lookup(user: &str, group: Option<&str>) -> Result<Self, String> {
+        let name = CString::new(user).err_tostring()?;
+        let mut buffer = vec![0 as libc::c_char; ENTRY_BUFFER];
+        let mut entry = MaybeUninit::<libc::passwd>::uninit();
+        let mut found = ptr::null_mut();
+        // The entry points into buffer, which outlives its use below
+        let result = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                entry.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        if result != 0 {
+            return Err(format!("{user}: {}", io::Error::from_raw_os_error(result)));
+        }
+        if found.is_null() {
+            return Err(format!("unknown user {user}"));
+        }
+        let entry = unsafe { entry.assume_init() };
+        let gid = match group {
+            Some(group) => lookup_group(group)?,
+            None => entry.pw_gid,
+        };
+        Ok(Self {
+            uid: entry.pw_uid,
+            gid,
+        })
+    }
+
+    // Switch to the account for good, including supplementary groups
+    pub fn switch(&self) -> Result<(), String> {
+        let groups = [self.gid];
+        unsafe {
+            if libc::setgroups(groups.len(), groups.as_ptr()) != 0 {
+                return Err(format!("setgroups: {}", io::Error::last_os_error()));
+            }
+            if libc::setgid(self.gid) != 0 {
+                return Err(format!("setgid: {}", io::Error::last_os_error()));
+            }
+            if libc::setuid(self.uid) != 0 {
+                return Err(format!("setuid: {}", io::Error::last_os_error()));
+            }
+            // Regaining root must fail now
+            if self.uid != 0 && libc::setuid(0) == 0 {
+                return Err(String::from("privileges could be regained after setuid"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lookup_group(group: &str) -> Result<libc::gid_t, String> {
+    let name = CString::new(group).err_tostring()?;
+    let mut buffer = vec![0 as libc::c_char; ENTRY_BUFFER];
+    let mut entry = MaybeUninit::<libc::group>::uninit();
+    let mut found = ptr::null_mut();
+    let result = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            entry.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        )
+    };
+    if result != 0 {
+        return Err(format!("{group}: {}", io::Error::from_raw_os_error(result)));
+    }
+    if found.is_null() {
+        return Err(format!("unknown group {group}"));
+    }
+    Ok(unsafe { entry.assume_init() }.gr_gid)
+}
+
+// Seccomp filter failing every syscall outside ALLOWED_SYSCALLS with EPERM,
+// and FILE_SYSCALLS with files. Failing instead of killing lets a missed
+// syscall surface as an error.
+pub fn syscall_filter(files: bool) -> Result<BpfProgram, String> {
+    let file_syscalls = if files { FILE_SYSCALLS } else { &[] };
+    let rules = ALLOWED_SYSCALLS
+        .iter()
+        .chain(file_syscalls)
+        .map(|syscall| (*syscall, Vec::new()))
+        .collect::<BTreeMap<_, _>>();
+    let filter = SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(libc::EPERM as u32),
+        SeccompAction::Allow,
+        std::env::consts::ARCH.try_into().err_tostring()?,
+    )
+    .err_tostring()?;
+    filter.try_into().err_tostring()
+}
+
+// Deny filesystem access outside writable, new TCP binds and connections,
+// and signals to other processes, then apply the syscall filter. Landlock
+// only covers the calling thread and threads it starts later, so this runs
+// before any other thread exists.
+pub fn restrict(writable: &[PathBuf]) -> Result<(), String> {
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(LANDLOCK_ABI))
+        .err_tostring()?
+        .handle_access(AccessNet::from_all(LANDLOCK_ABI))
+        .err_tostring()?
+        .scope(Scope::Signal)
+        .err_tostring()?
+        .create()
+        .err_tostring()?
+        .add_rules(path_beneath_rules(
+            writable,
+            AccessFs::from_all(LANDLOCK_ABI),
+        ))
+        .err_tostring()?
+        .restrict_self()
+        .err_tostring()?;
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => {}
+        RulesetStatus::PartiallyEnforced => warn!("Landlock is only partially supported"),
+        RulesetStatus::NotEnforced => {
+            warn!("Landlock is not available, the filesystem stays accessible")
+        }
+    }
+    apply_filter_all_threads(&syscall_filter(!writable.is_empty())?).err_tostring()
+}
@@ -0,0 +1,101 @@
+use super::super::parse::ActionType;
+
+// Sequence numbers remembered below the highest one, to tell duplicates
+// from late arrivals
+const SEQUENCE_WINDOW: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrival {
+    InOrder,
+    // This many messages before it never arrived
+    Gap(u64),
+    Duplicate,
+    // Arrived after a later message
+    Reordered,
+}
+
+// Classifies the sequence numbers of one connection
+#[derive(Default)]
+pub struct SequenceTracker {
+    // One past the highest sequence seen
+    next: Option<u64>,
+    // Bit n is set when next - 1 - n has arrived
+    seen: u64,
+}
+
+impl SequenceTracker {
+    pub fn arrive(&mut self, sequence: u64) -> Arrival {
+        let Some(next) = self.next else {
+            self.next = Some(sequence.saturating_add(1));
+            self.seen = 1;
+            return Arrival::InOrder;
+        };
+        if sequence >= next {
+            let skipped = sequence - next;
+            let shift = skipped + 1;
+            self.seen = if shift >= SEQUENCE_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            } | 1;
+            self.next = Some(sequence.saturating_add(1));
+            return match skipped {
+                0 => Arrival::InOrder,
+                skipped => Arrival::Gap(skipped),
+            };
+        }
+        let age = next - 1 - sequence;
+        if age >= SEQUENCE_WINDOW {
+            return Arrival::Reordered;
+        }
+        if self.seen & (1 << age) != 0 {
+            return Arrival::Duplicate;
+        }
+        self.seen |= 1 << age;
+        Arrival::Reordered
+    }
+}
+
+// Last known contact of each device, to tell whether a lost message could
+// have been a transition
+#[derive(Default)]
+pub struct Contacts {
+    // hover, down and button
+    stylus: Option<(bool, bool, bool)>,
+    // length and tracking ids
+    finger: Option<(i32, [i32; 12])>,
+}
+
+impl Contacts {
+    pub fn update(&mut self, action: &ActionType) {
+        match action {
+            ActionType::Stylus(pen_data) => {
+                self.stylus = Some((pen_data.hover, pen_data.down, pen_data.button));
+            }
+            ActionType::StylusBatch(batch_data) => {
+                self.stylus = Some((batch_data.hover, batch_data.down, batch_data.button));
+            }
+            ActionType::Finger(finger_data) => {
+                let tracking = finger_data.touchs.map(|touch| touch.tracking_id);
+                self.finger = Some((finger_data.length, tracking));
+            }
+            _ => {}
+        }
+    }
+
+    // Whether action shows every active contact unchanged. Otherwise a
+    // transition may have been lost with the missing messages.
+    pub fn confirmed_by(&self, action: &ActionType) -> bool {
+        let mut after = Contacts::default();
+        after.update(action);
+        let stylus_ok = match self.stylus {
+            Some((hover, down, _)) if hover || down => after.stylus == self.stylus,
+            _ => true,
+        };
+        let finger_ok = match self.finger {
+            Some((length, _)) if length != 0 => after.finger == self.finger,
+            _ => true,
+        };
+        stylus_ok && finger_ok
+    }
+}
@@ -0,0 +1,54 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+// Samples kept for the rolling percentiles
+const LATENCY_SAMPLES: usize = 512;
+
+#[derive(Default)]
+pub struct LatencyWindow {
+    // Microseconds, oldest first
+    samples: VecDeque<u32>,
+}
+
+impl LatencyWindow {
+    pub fn push(&mut self, micros: u64) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(micros.min(u32::MAX as u64) as u32);
+    }
+
+    // p50, p95 and p99 of the current window
+    pub fn percentiles(&self) -> Option<[u32; 3]> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u32> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let at = |percent: usize| sorted[(sorted.len() - 1) * percent / 100];
+        Some([at(50), at(95), at(99)])
+    }
+}
+
+// Counters for one connection, shared between it and the backend thread
+#[derive(Default)]
+pub struct SessionStats {
+    // Messages received from the client
+    pub messages: u64,
+    pub network: LatencyWindow,
+    pub processing: LatencyWindow,
+    // Stale samples dropped while the devices were behind
+    pub coalesced: u64,
+    // Sequence numbers that never arrived, arrived twice or arrived late
+    pub lost: u64,
+    pub duplicates: u64,
+    pub reordered: u64,
+    // Device resets after losing what may have been a transition
+    pub resyncs: u64,
+    // Messages over the size, rate or field limits
+    pub rejected: u64,
+}
+
+pub type SharedStats = Arc<Mutex<SessionStats>>;
@@ -0,0 +1,154 @@
+use std::{
+    env,
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::net::{TcpListener, UdpSocket, UnixListener};
+use tracing::warn;
+
+use super::{super::utility::ErrToString, ticker, worker::WorkerHandle};
+
+// First descriptor passed by socket activation
+const LISTEN_FDS_START: i32 = 3;
+
+// Sockets systemd passed in, with their FileDescriptorName=
+pub struct ActivatedSockets(Vec<(String, OwnedFd)>);
+
+impl ActivatedSockets {
+    // Take the sockets from LISTEN_FDS, if they are meant for this process.
+    // The variables are cleared so children do not take them again.
+    pub fn from_env() -> Result<Self, String> {
+        let pid = env::var("LISTEN_PID").ok();
+        let count = env::var("LISTEN_FDS").ok();
+        let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        let (Some(pid), Some(count)) = (pid, count) else {
+            return Ok(Self(Vec::new()));
+        };
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return Ok(Self(Vec::new()));
+        }
+        let count = count
+            .parse::<i32>()
+            .map_err(|_| format!("invalid LISTEN_FDS {count}"))?;
+        let mut names = names.split(':');
+        let sockets = (LISTEN_FDS_START..LISTEN_FDS_START + count)
+            .map(|fd| {
+                let name = String::from(names.next().unwrap_or_default());
+                // Passed to this process by systemd, nothing else owns them
+                (name, unsafe { OwnedFd::from_raw_fd(fd) })
+            })
+            .collect();
+        Ok(Self(sockets))
+    }
+
+    // Take the socket named name
+    pub fn take(&mut self, name: &str) -> Option<OwnedFd> {
+        let index = self.0.iter().position(|(other, _)| other == name)?;
+        Some(self.0.remove(index).1)
+    }
+
+    // Take any remaining socket, whatever its name
+    pub fn take_any(&mut self) -> Option<OwnedFd> {
+        (!self.0.is_empty()).then(|| self.0.remove(0).1)
+    }
+
+    pub fn take_tcp(&mut self, name: &str) -> Result<Option<TcpListener>, String> {
+        self.take(name).map(tcp_listener).transpose()
+    }
+
+    pub fn take_udp(&mut self, name: &str) -> Result<Option<UdpSocket>, String> {
+        let Some(fd) = self.take(name) else {
+            return Ok(None);
+        };
+        let socket = std::net::UdpSocket::from(fd);
+        socket.set_nonblocking(true).err_tostring()?;
+        UdpSocket::from_std(socket).map(Some).err_tostring()
+    }
+
+    pub fn take_unix(&mut self, name: &str) -> Result<Option<UnixListener>, String> {
+        let Some(fd) = self.take(name) else {
+            return Ok(None);
+        };
+        let listener = std::os::unix::net::UnixListener::from(fd);
+        listener.set_nonblocking(true).err_tostring()?;
+        UnixListener::from_std(listener).map(Some).err_tostring()
+    }
+}
+
+pub fn tcp_listener(fd: OwnedFd) -> Result<TcpListener, String> {
+    let listener = std::net::TcpListener::from(fd);
+    listener.set_nonblocking(true).err_tostring()?;
+    TcpListener::from_std(listener).err_tostring()
+}
+
+// Where service state is reported to systemd
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+
+impl Notifier {
+    // From NOTIFY_SOCKET, None when not started by systemd
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match env::var_os("NOTIFY_SOCKET") {
+            Some(path) => Self::new(PathBuf::from(path)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // A leading @ names an abstract socket
+    pub fn new(path: PathBuf) -> Result<Self, String> {
+        let address = match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        }
+        .err_tostring()?;
+        let socket = UnixDatagram::unbound().err_tostring()?;
+        Ok(Self { socket, address })
+    }
+
+    // Send state such as READY=1, WATCHDOG=1 or STOPPING=1
+    pub fn notify(&self, state: &str) -> Result<(), String> {
+        self.socket
+            .send_to_addr(state.as_bytes(), &self.address)
+            .err_tostring()?;
+        Ok(())
+    }
+}
+
+// How often to ping the watchdog, half its timeout from WATCHDOG_USEC
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+// Ping the watchdog while the backend thread keeps up. A stuck device
+// stops the pings, and systemd restarts the service.
+pub async fn watchdog(notifier: Arc<Notifier>, period: Duration, worker: WorkerHandle) {
+    let mut tick = ticker(period);
+    loop {
+        tick.tick().await;
+        if !worker.alive().await {
+            return;
+        }
+        if let Err(err) = notifier.notify("WATCHDOG=1") {
+            warn!("watchdog: {err}");
+        }
+    }
+}
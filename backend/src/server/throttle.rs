@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+// Lets the first burst of a repeated log line through every window and
+// counts the rest, so a broken client cannot flood the log
+#[derive(Debug)]
+pub struct Throttle {
+    burst: u32,
+    window: Duration,
+    window_start: Option<Instant>,
+    count: u32,
+    suppressed: u64,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(10))
+    }
+}
+
+impl Throttle {
+    pub fn new(burst: u32, window: Duration) -> Self {
+        Self {
+            burst,
+            window,
+            window_start: None,
+            count: 0,
+            suppressed: 0,
+        }
+    }
+
+    // Some with the number of lines suppressed since the last one logged,
+    // None when this one should be suppressed too
+    pub fn allow(&mut self, now: Instant) -> Option<u64> {
+        let expired = self
+            .window_start
+            .is_none_or(|start| now.saturating_duration_since(start) >= self.window);
+        if expired {
+            self.window_start = Some(now);
+            self.count = 0;
+        }
+        if self.count >= self.burst {
+            self.suppressed += 1;
+            return None;
+        }
+        self.count += 1;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    env, fs,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+use tokio_rustls::{
+    rustls::{
+        self,
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    },
+    TlsAcceptor,
+};
+use tracing::info;
+
+use super::super::utility::ErrToString;
+
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+// $XDG_CONFIG_HOME/pendroid or ~/.config/pendroid
+pub fn default_tls_dir() -> Result<PathBuf, String> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(env::var_os("HOME").ok_or("HOME is not set")?).join(".config"),
+    };
+    Ok(config.join("pendroid"))
+}
+
+// SHA-256 of the DER certificate, as AB:CD:...
+pub fn fingerprint(cert: &CertificateDer) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// Self-signed certificate the app pins by fingerprint instead of trusting a CA
+#[derive(Clone)]
+pub struct TlsIdentity {
+    pub acceptor: TlsAcceptor,
+    pub fingerprint: String,
+}
+
+impl TlsIdentity {
+    // Load the certificate from dir, generating it on first run
+    pub fn load_or_create(dir: &Path) -> Result<Self, String> {
+        let cert_path = dir.join(CERT_FILE);
+        let key_path = dir.join(KEY_FILE);
+        if !cert_path.exists() || !key_path.exists() {
+            generate(dir, &cert_path, &key_path)?;
+        }
+
+        let cert = CertificateDer::from_pem_file(&cert_path)
+            .map_err(|err| format!("{}: {err}", cert_path.display()))?;
+        let key = PrivateKeyDer::from_pem_file(&key_path)
+            .map_err(|err| format!("{}: {err}", key_path.display()))?;
+        let fingerprint = fingerprint(&cert);
+
+        let config =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .err_tostring()?
+                .with_no_client_auth()
+                .with_single_cert(vec![cert], key)
+                .err_tostring()?;
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            fingerprint,
+        })
+    }
+}
+
+fn generate(dir: &Path, cert_path: &Path, key_path: &Path) -> Result<(), String> {
+    let names = vec![String::from("pendroid"), String::from("localhost")];
+    let certified = rcgen::generate_simple_self_signed(names).err_tostring()?;
+    fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+
+    // Only the owner may read the private key
+    let mut key_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(key_path)
+        .map_err(|err| format!("{}: {err}", key_path.display()))?;
+    key_file
+        .write_all(certified.signing_key.serialize_pem().as_bytes())
+        .err_tostring()?;
+    fs::write(cert_path, certified.cert.pem())
+        .map_err(|err| format!("{}: {err}", cert_path.display()))?;
+    info!("Generated TLS certificate in {}", dir.display());
+    Ok(())
+}
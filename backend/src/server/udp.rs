@@ -0,0 +1,173 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::SocketAddr,
+    sync::Arc,
+    time::Instant,
+};
+
+use tokio::{net::UdpSocket, time::sleep_until};
+use tracing::warn;
+
+use super::{
+    super::{
+        metrics::{ActiveConnection, METRICS},
+        parse::{action_encode, action_parse_meta, AckData, ActionType},
+    },
+    audit::AuditRecord,
+    client::ClientState,
+    throttle::Throttle,
+    ticker,
+    worker::WorkerHandle,
+    ServerConfig,
+};
+
+// Largest UDP payload, so oversized datagrams are seen whole and rejected
+// by the limits instead of truncated
+const MAX_DATAGRAM: usize = 65536;
+
+struct Peer {
+    client: ClientState,
+    last_seen: Instant,
+    record: AuditRecord,
+    _active: ActiveConnection,
+}
+
+impl Peer {
+    // Log why the peer is dropped and write its audit record
+    fn end(mut self, peer: &SocketAddr, reason: &str, config: &ServerConfig) {
+        warn!(%peer, "{reason}");
+        self.record.reason = Some(String::from(reason));
+        config.audit(&self.record);
+    }
+}
+
+// Serve clients sending one protocol line per datagram, text or binary.
+// Every datagram carries a sequence number and is acknowledged with A<seq>,
+// so clients resend transitions until they are acknowledged. Late samples
+// are dropped, duplicates of resent datagrams are ignored.
+pub async fn serve_udp(
+    socket: UdpSocket,
+    worker: WorkerHandle,
+    config: Arc<ServerConfig>,
+) -> Result<(), String> {
+    let mut peers: HashMap<SocketAddr, Peer> = HashMap::new();
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    let mut throttle = Throttle::default();
+    let mut ping = ticker(config.heartbeat.interval);
+    let mut report = ticker(config.report_interval);
+
+    loop {
+        let release = peers
+            .values()
+            .filter_map(|state| state.client.next_release())
+            .min();
+        tokio::select! {
+            _ = ping.tick() => {
+                // Peers are dropped when silent or taken over, releasing their input
+                let dropped = peers
+                    .iter_mut()
+                    .filter_map(|(peer, state)| {
+                        if state.last_seen.elapsed() >= config.heartbeat.timeout {
+                            return Some((*peer, "heartbeat timeout"));
+                        }
+                        let session = state.client.session();
+                        session.is_kicked().then(|| (*peer, session.kick_reason()))
+                    })
+                    .collect::<Vec<_>>();
+                for (peer, reason) in dropped {
+                    if let Some(state) = peers.remove(&peer) {
+                        state.end(&peer, reason, &config);
+                    }
+                }
+                for (peer, state) in &peers {
+                    send(&socket, peer, state.client.clock_probe()).await;
+                }
+            }
+            _ = report.tick() => {
+                for (peer, state) in &peers {
+                    if let Some(status) = state.client.latency_report() {
+                        send(&socket, peer, status).await;
+                    }
+                }
+            }
+            _ = sleep_until(release.unwrap_or_else(Instant::now).into()), if release.is_some() => {
+                for state in peers.values_mut() {
+                    state.client.release().await;
+                }
+            }
+            result = socket.recv_from(&mut buffer) => {
+                let received = Instant::now();
+                let (length, peer) = match result {
+                    Ok(datagram) => datagram,
+                    Err(err) => {
+                        warn!("{err}");
+                        continue;
+                    }
+                };
+                let Ok(text) = std::str::from_utf8(&buffer[..length]) else {
+                    malformed(&mut throttle, &peer, "datagram is not UTF-8", received);
+                    continue;
+                };
+                let Some(sequence) = action_parse_meta(text)
+                    .ok()
+                    .and_then(|(_, meta)| meta.sequence)
+                else {
+                    malformed(&mut throttle, &peer, "datagram without sequence number", received);
+                    continue;
+                };
+
+                let state = match peers.entry(peer) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let mut record = AuditRecord::new("udp", peer.to_string());
+                        let session = match worker.connect(None).await {
+                            Ok(session) => session,
+                            Err(err) => {
+                                warn!(%peer, "{err}");
+                                record.reason = Some(err);
+                                config.audit(&record);
+                                continue;
+                            }
+                        };
+                        record.accepted = true;
+                        record.stats = Some(session.stats().clone());
+                        let client = ClientState::new(peer.to_string(), session, &config);
+                        entry.insert(Peer {
+                            client,
+                            last_seen: received,
+                            record,
+                            _active: METRICS.connection("udp"),
+                        })
+                    }
+                };
+                state.last_seen = received;
+
+                // Acknowledged even when dropped, the client can stop resending
+                send(&socket, &peer, action_encode(&ActionType::Ack(AckData { sequence }))).await;
+                if let Some(reply) = state.client.receive(text, received).await {
+                    send(&socket, &peer, reply).await;
+                }
+                if state.client.exceeded_limits() {
+                    if let Some(state) = peers.remove(&peer) {
+                        state.end(&peer, "too many rejected messages", &config);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send(socket: &UdpSocket, peer: &SocketAddr, text: String) {
+    if let Err(err) = socket.send_to(text.as_bytes(), peer).await {
+        warn!(%peer, "{err}");
+    }
+}
+
+// Stray datagrams come from anyone, a few are logged per window
+fn malformed(throttle: &mut Throttle, peer: &SocketAddr, message: &str, received: Instant) {
+    match throttle.allow(received) {
+        Some(0) => warn!(%peer, "{message}"),
+        Some(suppressed) => warn!(%peer, suppressed, "{message}"),
+        None => {}
+    }
+}
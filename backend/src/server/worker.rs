@@ -0,0 +1,602 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+};
+use tracing::error;
+
+use super::{
+    super::{
+        backend::{DeviceIdentity, InputBackend},
+        metrics::METRICS,
+        parse::ActionType,
+    },
+    stats::SharedStats,
+    throttle::Throttle,
+};
+
+pub type ClientId = u64;
+
+// Commands waiting for the backend thread. When full, connections stop
+// reading from their sockets until the backend catches up.
+const QUEUE_CAPACITY: usize = 1024;
+// Queued commands at which the devices are considered behind and stale
+// samples get coalesced
+const COALESCE_BACKLOG: usize = 16;
+
+const BACKEND_STOPPED: &str = "backend stopped";
+
+// What to do when a client connects while another one is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientPolicy {
+    // Refuse new clients until the current one disconnects
+    Reject,
+    // Disconnect the current client and hand the devices to the new one
+    Takeover,
+    // Create a separate set of devices for every client
+    PerClient,
+}
+
+impl FromStr for ClientPolicy {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "reject" => Ok(Self::Reject),
+            "takeover" => Ok(Self::Takeover),
+            "per-client" => Ok(Self::PerClient),
+            _ => Err(format!(
+                "unknown client policy {text}, expected reject, takeover or per-client"
+            )),
+        }
+    }
+}
+
+// A set of input devices driven by one client at a time
+pub trait Devices: Send + 'static {
+    // sample_time is when the client took the sample, in server clock
+    // microseconds, if the client sends timestamps
+    fn execute(&mut self, action: &ActionType, sample_time: Option<u64>) -> Result<(), String>;
+    // Release everything still pressed
+    fn reset(&mut self) -> Result<(), String>;
+}
+
+impl Devices for InputBackend {
+    fn execute(&mut self, action: &ActionType, sample_time: Option<u64>) -> Result<(), String> {
+        InputBackend::execute_at(self, action, sample_time)
+    }
+    fn reset(&mut self) -> Result<(), String> {
+        InputBackend::reset(self)
+    }
+}
+
+pub type CreateDevices<D> = Box<dyn FnMut(&DeviceIdentity) -> Result<D, String> + Send>;
+
+enum Command {
+    Connect {
+        id: ClientId,
+        label: Option<String>,
+        stats: SharedStats,
+        kick: oneshot::Sender<&'static str>,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    Message {
+        id: ClientId,
+        action: ActionType,
+        received: Instant,
+        sample_time: Option<u64>,
+    },
+    // Release the client's input without disconnecting it
+    Resync {
+        id: ClientId,
+    },
+    Disconnect {
+        id: ClientId,
+    },
+    // Answered once every queued command before it was handled
+    Ping {
+        reply: oneshot::Sender<()>,
+    },
+    // Release every device, drop every client and stop. Answered once the
+    // devices are destroyed.
+    Shutdown {
+        reply: oneshot::Sender<()>,
+    },
+}
+
+// Pen or finger state that must reach the devices even when samples are
+// coalesced. None for actions that are never coalesced.
+fn coalesce_state(action: &ActionType) -> Option<Vec<i32>> {
+    match action {
+        ActionType::Stylus(pen_data) if pen_data.hover && !pen_data.down => {
+            Some(vec![pen_data.button as i32])
+        }
+        ActionType::Finger(finger_data) => {
+            let mut state = vec![finger_data.length];
+            for touch in &finger_data.touchs {
+                state.extend([touch.slot, touch.tracking_id]);
+            }
+            Some(state)
+        }
+        _ => None,
+    }
+}
+
+// Key of a command that may be coalesced, the client and its input state
+fn coalesce_key(command: &Command) -> Option<(ClientId, bool, Vec<i32>)> {
+    let Command::Message { id, action, .. } = command else {
+        return None;
+    };
+    let is_stylus = matches!(action, ActionType::Stylus(_));
+    Some((*id, is_stylus, coalesce_state(action)?))
+}
+
+// Drop samples whose neighbours on both sides carry the same state, so only
+// the newest position of a run is emitted and no transition is lost.
+// Returns the dropped count per client.
+fn coalesce(commands: &mut VecDeque<Command>) -> HashMap<ClientId, u64> {
+    let keys: Vec<_> = commands.iter().map(coalesce_key).collect();
+    let mut dropped = HashMap::new();
+    let mut index = 0;
+    commands.retain(|_| {
+        let current = index;
+        index += 1;
+        let (Some(key), Some(Some(before)), Some(Some(after))) = (
+            &keys[current],
+            current.checked_sub(1).map(|before| &keys[before]),
+            keys.get(current + 1),
+        ) else {
+            return true;
+        };
+        if before != key || after != key {
+            return true;
+        }
+        *dropped.entry(key.0).or_insert(0) += 1;
+        false
+    });
+    dropped
+}
+
+struct Client<D> {
+    // Sending a reason or dropping this ends the connection
+    kick: oneshot::Sender<&'static str>,
+    stats: SharedStats,
+    // Devices and their identity index under the per-client policy
+    devices: Option<(u16, D)>,
+}
+
+// Owns every input device, fed by the connections through a channel
+struct Worker<D> {
+    policy: ClientPolicy,
+    create: CreateDevices<D>,
+    shared: Option<D>,
+    owner: Option<ClientId>,
+    clients: HashMap<ClientId, Client<D>>,
+    // A device failing once usually fails for every message after
+    errors: Throttle,
+}
+
+impl<D: Devices> Worker<D> {
+    // Lowest identity index not used by a connected client, indices of
+    // disconnected clients are reused
+    fn free_index(&self) -> Result<u16, String> {
+        (1..=DeviceIdentity::MAX_INDEX)
+            .find(|index| {
+                !self
+                    .clients
+                    .values()
+                    .any(|client| matches!(client.devices, Some((used, _)) if used == *index))
+            })
+            .ok_or_else(|| String::from("too many clients"))
+    }
+
+    fn connect(
+        &mut self,
+        id: ClientId,
+        label: Option<String>,
+        stats: SharedStats,
+        kick: oneshot::Sender<&'static str>,
+    ) -> Result<(), String> {
+        let mut devices = None;
+        match self.policy {
+            ClientPolicy::Reject => {
+                if self.owner.is_some() {
+                    return Err(String::from("another client is already connected"));
+                }
+                self.owner = Some(id);
+            }
+            ClientPolicy::Takeover => {
+                if let Some(old) = self.owner.replace(id) {
+                    self.release_shared();
+                    if let Some(client) = self.clients.remove(&old) {
+                        let _ = client.kick.send("taken over by another client");
+                    }
+                }
+            }
+            ClientPolicy::PerClient => {
+                let identity = DeviceIdentity::new(self.free_index()?, label.as_deref());
+                devices = Some((identity.index, (self.create)(&identity)?));
+            }
+        }
+        self.clients.insert(
+            id,
+            Client {
+                kick,
+                stats,
+                devices,
+            },
+        );
+        Ok(())
+    }
+
+    fn devices(&mut self, id: ClientId) -> Option<&mut D> {
+        if self.owner == Some(id) {
+            return self.shared.as_mut();
+        }
+        self.clients
+            .get_mut(&id)?
+            .devices
+            .as_mut()
+            .map(|(_, devices)| devices)
+    }
+
+    fn message(
+        &mut self,
+        id: ClientId,
+        action: ActionType,
+        received: Instant,
+        sample_time: Option<u64>,
+    ) {
+        let Some(devices) = self.devices(id) else {
+            return;
+        };
+        if let Err(err) = devices.execute(&action, sample_time) {
+            METRICS.emit_error();
+            match self.errors.allow(Instant::now()) {
+                Some(0) => error!("{err}"),
+                Some(suppressed) => error!(suppressed, "{err}"),
+                None => {}
+            }
+            return;
+        }
+
+        // Receive to emit time for input samples
+        if matches!(
+            action,
+            ActionType::Stylus(_) | ActionType::StylusBatch(_) | ActionType::Finger(_)
+        ) {
+            let micros = received.elapsed().as_micros() as u64;
+            METRICS.processing_latency(micros);
+            if let Some(client) = self.clients.get(&id) {
+                client.stats.lock().unwrap().processing.push(micros);
+            }
+        }
+        match &action {
+            ActionType::Stylus(_) => METRICS.stylus_samples(1, Instant::now()),
+            ActionType::StylusBatch(batch) => {
+                METRICS.stylus_samples(batch.samples.len(), Instant::now())
+            }
+            _ => {}
+        }
+    }
+
+    fn resync(&mut self, id: ClientId) {
+        if let Some(Err(err)) = self.devices(id).map(D::reset) {
+            METRICS.emit_error();
+            error!("{err}");
+        }
+    }
+
+    fn release_shared(&mut self) {
+        if let Some(Err(err)) = self.shared.as_mut().map(D::reset) {
+            METRICS.emit_error();
+            error!("{err}");
+        }
+    }
+
+    fn disconnect(&mut self, id: ClientId) {
+        // Per-client devices are released, then destroyed with the client
+        if let Some(Client {
+            devices: Some((_, mut devices)),
+            ..
+        }) = self.clients.remove(&id)
+        {
+            if let Err(err) = devices.reset() {
+                METRICS.emit_error();
+                error!("{err}");
+            }
+        }
+        if self.owner == Some(id) {
+            self.owner = None;
+            self.release_shared();
+        }
+    }
+
+    // Release everything still pressed on every device set, and drop the
+    // clients so their connections close
+    fn release_all(&mut self) {
+        self.release_shared();
+        for (_, Client { kick, devices, .. }) in self.clients.drain() {
+            let _ = kick.send("server shutting down");
+            if let Some((_, mut devices)) = devices {
+                if let Err(err) = devices.reset() {
+                    METRICS.emit_error();
+                    error!("{err}");
+                }
+            }
+        }
+        self.owner = None;
+    }
+
+    fn run(mut self, mut receiver: mpsc::Receiver<Command>) {
+        let mut pending = VecDeque::new();
+        let shutdown = loop {
+            if pending.is_empty() {
+                let Some(command) = receiver.blocking_recv() else {
+                    break None;
+                };
+                pending.push_back(command);
+                // The devices fell behind, catch up to the newest input
+                if receiver.len() >= COALESCE_BACKLOG {
+                    while let Ok(command) = receiver.try_recv() {
+                        pending.push_back(command);
+                    }
+                    for (id, count) in coalesce(&mut pending) {
+                        if let Some(client) = self.clients.get(&id) {
+                            client.stats.lock().unwrap().coalesced += count;
+                        }
+                    }
+                }
+            }
+            let Some(command) = pending.pop_front() else {
+                continue;
+            };
+            match command {
+                Command::Connect {
+                    id,
+                    label,
+                    stats,
+                    kick,
+                    reply,
+                } => {
+                    let _ = reply.send(self.connect(id, label, stats, kick));
+                }
+                Command::Message {
+                    id,
+                    action,
+                    received,
+                    sample_time,
+                } => self.message(id, action, received, sample_time),
+                Command::Resync { id } => self.resync(id),
+                Command::Disconnect { id } => self.disconnect(id),
+                Command::Ping { reply } => {
+                    let _ = reply.send(());
+                }
+                Command::Shutdown { reply } => {
+                    self.release_all();
+                    break Some(reply);
+                }
+            }
+        };
+        // Destroys the devices before the shutdown is answered
+        drop(self);
+        if let Some(reply) = shutdown {
+            let _ = reply.send(());
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkerHandle {
+    sender: mpsc::Sender<Command>,
+    next_id: Arc<AtomicU64>,
+}
+
+// Shared devices are created, the backend thread is not started yet
+pub struct PendingWorker<D> {
+    worker: Worker<D>,
+}
+
+impl<D: Devices> PendingWorker<D> {
+    // Start the backend thread. uinput calls block, so it runs on its own
+    // thread instead of the async runtime.
+    pub fn start(self) -> Result<WorkerHandle, String> {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let worker = self.worker;
+        thread::Builder::new()
+            .name(String::from("pendroid-backend"))
+            .spawn(move || worker.run(receiver))
+            .map_err(|err| err.to_string())?;
+        Ok(WorkerHandle {
+            sender,
+            next_id: Arc::new(AtomicU64::new(0)),
+        })
+    }
+}
+
+impl WorkerHandle {
+    // Start the backend thread with uinput devices. prediction is how far
+    // ahead the stylus is reported.
+    pub fn spawn(policy: ClientPolicy, prediction: Option<Duration>) -> Result<Self, String> {
+        Self::prepare(policy, prediction)?.start()
+    }
+
+    pub fn spawn_with<D: Devices>(
+        policy: ClientPolicy,
+        create: CreateDevices<D>,
+    ) -> Result<Self, String> {
+        Self::prepare_with(policy, create)?.start()
+    }
+
+    // Create the shared uinput devices without starting the backend thread
+    pub fn prepare(
+        policy: ClientPolicy,
+        prediction: Option<Duration>,
+    ) -> Result<PendingWorker<InputBackend>, String> {
+        Self::prepare_with(
+            policy,
+            Box::new(move |identity: &DeviceIdentity| {
+                let mut backend = InputBackend::with_identity(identity)?;
+                backend.set_prediction(prediction);
+                Ok(backend)
+            }),
+        )
+    }
+
+    // Shared devices are created up front, per-client ones on connect
+    pub fn prepare_with<D: Devices>(
+        policy: ClientPolicy,
+        mut create: CreateDevices<D>,
+    ) -> Result<PendingWorker<D>, String> {
+        let shared = match policy {
+            ClientPolicy::PerClient => None,
+            _ => Some(create(&DeviceIdentity::default())?),
+        };
+        Ok(PendingWorker {
+            worker: Worker {
+                policy,
+                create,
+                shared,
+                owner: None,
+                clients: HashMap::new(),
+                errors: Throttle::default(),
+            },
+        })
+    }
+
+    // The label names per-client devices, shared devices ignore it
+    pub async fn connect(&self, label: Option<String>) -> Result<Session, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (kick, kicked) = oneshot::channel();
+        let (reply, result) = oneshot::channel();
+        let stats = SharedStats::default();
+        self.sender
+            .send(Command::Connect {
+                id,
+                label,
+                stats: stats.clone(),
+                kick,
+                reply,
+            })
+            .await
+            .map_err(|_| String::from("backend stopped"))?;
+        result
+            .await
+            .map_err(|_| String::from("backend stopped"))??;
+        Ok(Session {
+            id,
+            sender: self.sender.clone(),
+            stats,
+            kicked: Some(kicked),
+            kick_reason: BACKEND_STOPPED,
+        })
+    }
+
+    // Whether the backend thread still handles commands, waiting while the
+    // queue is full
+    pub async fn alive(&self) -> bool {
+        let (reply, answer) = oneshot::channel();
+        if self.sender.send(Command::Ping { reply }).await.is_err() {
+            return false;
+        }
+        answer.await.is_ok()
+    }
+
+    // Release all input and destroy the devices. Later messages and
+    // connections are refused.
+    pub async fn shutdown(&self) {
+        let (reply, done) = oneshot::channel();
+        if self.sender.send(Command::Shutdown { reply }).await.is_ok() {
+            let _ = done.await;
+        }
+    }
+}
+
+// A connected client. Dropping it disconnects and releases its input state.
+pub struct Session {
+    id: ClientId,
+    sender: mpsc::Sender<Command>,
+    stats: SharedStats,
+    kicked: Option<oneshot::Receiver<&'static str>>,
+    kick_reason: &'static str,
+}
+
+impl Session {
+    // Waits while the backend queue is full. received is when the message
+    // arrived, for latency statistics. sample_time is the client timestamp
+    // mapped onto the server clock.
+    pub async fn send(&self, action: ActionType, received: Instant, sample_time: Option<u64>) {
+        let _ = self
+            .sender
+            .send(Command::Message {
+                id: self.id,
+                action,
+                received,
+                sample_time,
+            })
+            .await;
+    }
+
+    // Release everything the client holds, its next messages press again
+    pub async fn resync(&self) {
+        let _ = self.sender.send(Command::Resync { id: self.id }).await;
+    }
+
+    pub fn stats(&self) -> &SharedStats {
+        &self.stats
+    }
+
+    // Whether the backend dropped this client, without waiting
+    pub fn is_kicked(&mut self) -> bool {
+        match self.kicked.as_mut().map(oneshot::Receiver::try_recv) {
+            Some(Err(oneshot::error::TryRecvError::Empty)) => false,
+            Some(result) => {
+                self.kick_reason = result.unwrap_or(BACKEND_STOPPED);
+                self.kicked = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    // Resolves with the reason when the backend drops this client
+    pub async fn kicked(&mut self) -> &'static str {
+        if let Some(kicked) = self.kicked.as_mut() {
+            self.kick_reason = kicked.await.unwrap_or(BACKEND_STOPPED);
+            self.kicked = None;
+        }
+        self.kick_reason
+    }
+
+    // Why the backend dropped this client, once it did
+    pub fn kick_reason(&self) -> &'static str {
+        self.kick_reason
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let command = Command::Disconnect { id: self.id };
+        // The disconnect must not be lost, so wait for room in the background
+        if let Err(TrySendError::Full(command)) = self.sender.try_send(command) {
+            if let Ok(runtime) = Handle::try_current() {
+                let sender = self.sender.clone();
+                runtime.spawn(async move {
+                    let _ = sender.send(command).await;
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+
+use pendroid::server::{AuditLog, AuditRecord};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pendroid-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn record_fields_are_escaped() {
+    let mut record = AuditRecord::new("websocket", String::from("127.0.0.1:4000"));
+    record.label = Some(String::from("tab \"1\"\n\\"));
+    record.reason = Some(String::from("origin not allowed"));
+    let json = record.to_json();
+    assert!(json.starts_with("{\"started\":"), "{json}");
+    assert!(json.contains(",\"transport\":\"websocket\",\"peer\":\"127.0.0.1:4000\""));
+    assert!(json.contains(",\"origin\":null,\"label\":\"tab \\\"1\\\"\\n\\\\\",\"version\":null"));
+    assert!(json.ends_with(
+        ",\"accepted\":false,\"messages\":0,\"rejected\":0,\"reason\":\"origin not allowed\"}"
+    ));
+}
+
+#[test]
+fn log_rotates_and_keeps_old_files() {
+    let dir = temp_dir("audit");
+    let path = dir.join("audit.log");
+    let record = AuditRecord::new("tcp", String::from("127.0.0.1:4000"));
+    let line = record.to_json().len() as u64 + 1;
+
+    // Room for two records per file, two rotated files kept
+    let log = AuditLog::open(&path, line * 2 + line / 2, 2).unwrap();
+    for _ in 0..7 {
+        log.write(&record);
+    }
+    let lines = |name: &str| fs::read_to_string(dir.join(name)).unwrap().lines().count();
+    assert_eq!(lines("audit.log"), 1);
+    assert_eq!(lines("audit.log.1"), 2);
+    assert_eq!(lines("audit.log.2"), 2);
+    assert!(!dir.join("audit.log.3").exists());
+    assert_eq!(log.dir(), dir);
+}
@@ -0,0 +1,32 @@
+#![cfg(target_os = "linux")]
+
+use pendroid::server::ClockSync;
+
+#[test]
+fn shortest_round_trip_wins() {
+    let mut clock = ClockSync::default();
+    assert_eq!(clock.to_server(5_000), None);
+    clock.add_probe(1_000, 11_500, 2_000);
+    clock.add_probe(3_000, 15_000, 9_000);
+    assert_eq!(clock.offset(), Some(10_000));
+    assert_eq!(clock.to_server(20_000), Some(10_000));
+}
+
+#[test]
+fn out_of_range_times_are_dropped() {
+    let mut clock = ClockSync::default();
+    // Offsets past i64 are ignored instead of wrapping
+    clock.add_probe(0, u64::MAX, 0);
+    assert_eq!(clock.offset(), None);
+
+    clock.add_probe(0, 1_000, 0);
+    assert_eq!(clock.to_server(1_000), Some(0));
+    // Before the server started
+    assert_eq!(clock.to_server(999), None);
+
+    let mut clock = ClockSync::default();
+    clock.add_probe(1 << 63, 0, 1 << 63);
+    assert_eq!(clock.offset(), Some(i64::MIN));
+    // Past the end of the server clock
+    assert_eq!(clock.to_server(u64::MAX), None);
+}
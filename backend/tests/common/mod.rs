@@ -0,0 +1,123 @@
+// Mock devices and servers shared by the integration tests. Every test
+// binary uses a different part of it.
+#![allow(dead_code)]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use pendroid::{
+    action_encode_meta,
+    server::{serve_listener, ClientPolicy, CreateDevices, Devices, ServerConfig, WorkerHandle},
+    ActionMeta, ActionType, DeviceIdentity, ViewData,
+};
+use tokio::net::TcpListener;
+
+// Every call made on any device set, tagged with the set's identity index.
+// Shared devices have index 0.
+pub type Log = Arc<Mutex<Vec<(u16, String)>>>;
+
+// Logs actions with their sample time as metadata, resets and drops.
+// V0;0 stalls the devices so a backlog builds up behind it.
+pub struct MockDevices {
+    index: u16,
+    log: Log,
+}
+
+impl MockDevices {
+    pub fn new(index: u16, log: Log) -> Self {
+        Self { index, log }
+    }
+
+    fn push(&self, text: String) {
+        self.log.lock().unwrap().push((self.index, text));
+    }
+}
+
+impl Drop for MockDevices {
+    fn drop(&mut self) {
+        self.push(String::from("drop"));
+    }
+}
+
+impl Devices for MockDevices {
+    fn execute(&mut self, action: &ActionType, sample_time: Option<u64>) -> Result<(), String> {
+        if *action == view(0) {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        let meta = ActionMeta {
+            timestamp: sample_time,
+            ..ActionMeta::default()
+        };
+        self.push(action_encode_meta(action, &meta));
+        Ok(())
+    }
+    fn reset(&mut self) -> Result<(), String> {
+        self.push(String::from("reset"));
+        Ok(())
+    }
+}
+
+// Distinct actions that encode as Vn;n
+pub fn view(n: u32) -> ActionType {
+    ActionType::Screen(ViewData {
+        width: n,
+        height: n,
+    })
+}
+
+pub fn create_devices(log: &Log) -> CreateDevices<MockDevices> {
+    let log = log.clone();
+    Box::new(move |identity: &DeviceIdentity| Ok(MockDevices::new(identity.index, log.clone())))
+}
+
+pub fn spawn_worker(policy: ClientPolicy) -> (WorkerHandle, Log) {
+    let log = Log::default();
+    let worker = WorkerHandle::spawn_with(policy, create_devices(&log)).unwrap();
+    (worker, log)
+}
+
+// Serve WebSocket clients on a random local port, returns its ws:// url
+pub async fn start(config: ServerConfig) -> (String, Log) {
+    let (worker, log) = spawn_worker(config.policy);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { serve_listener(listener, worker, config).await });
+    (url, log)
+}
+
+// What reached the devices, without the index
+pub fn texts(log: &Log) -> Vec<String> {
+    log.lock()
+        .unwrap()
+        .iter()
+        .map(|(_, text)| text.clone())
+        .collect()
+}
+
+// The backend runs on its own thread, so wait for it to catch up
+pub async fn wait_for(log: &Log, expected: &[&str]) {
+    for _ in 0..200 {
+        if texts(log) == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(texts(log), expected);
+}
+
+// Same as wait_for, with the index of each device set
+pub async fn wait_for_tagged(log: &Log, expected: &[(u16, &str)]) {
+    let expected: Vec<(u16, String)> = expected
+        .iter()
+        .map(|(index, text)| (*index, String::from(*text)))
+        .collect();
+    for _ in 0..200 {
+        if *log.lock().unwrap() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(*log.lock().unwrap(), expected);
+}
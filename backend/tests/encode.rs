@@ -0,0 +1,276 @@
+use pendroid::{
+    action_encode, action_encode_meta, action_parse, action_parse_meta, AckData, ActionMeta,
+    ActionType, ClockData, ErrorData, ErrorKind, FingerData, HeartbeatData, LatencyData,
+    StylusBatchData, StylusData, StylusSample, Touch, ViewData,
+};
+use proptest::prelude::*;
+
+fn stylus_data() -> impl Strategy<Value = StylusData> {
+    (
+        (any::<bool>(), any::<bool>(), any::<bool>()),
+        (any::<i32>(), any::<i32>()),
+        (any::<i32>(), any::<i32>(), any::<i32>()),
+    )
+        .prop_map(
+            |((hover, down, button), (x, y), (tilt_x, tilt_y, pressure))| StylusData {
+                x,
+                y,
+                tilt_x,
+                tilt_y,
+                pressure,
+                down,
+                hover,
+                button,
+            },
+        )
+}
+
+fn stylus_batch_data() -> impl Strategy<Value = StylusBatchData> {
+    let sample = (prop::array::uniform5(any::<i32>()), any::<u32>()).prop_map(
+        |([x, y, tilt_x, tilt_y, pressure], age)| StylusSample {
+            x,
+            y,
+            tilt_x,
+            tilt_y,
+            pressure,
+            age,
+        },
+    );
+    (
+        (any::<bool>(), any::<bool>(), any::<bool>()),
+        prop::collection::vec(sample, 0..16),
+    )
+        .prop_map(|((hover, down, button), samples)| StylusBatchData {
+            down,
+            hover,
+            button,
+            samples,
+        })
+}
+
+fn touch() -> impl Strategy<Value = Touch> {
+    (any::<i32>(), any::<i32>(), any::<i32>(), any::<i32>()).prop_map(
+        |(x, y, slot, tracking_id)| Touch {
+            x,
+            y,
+            slot,
+            tracking_id,
+        },
+    )
+}
+
+// Any mix of default and arbitrary touches, including slot -1 in the middle
+fn finger_data() -> impl Strategy<Value = FingerData> {
+    let slot = prop_oneof![Just(Touch::default()), touch()];
+    (any::<i32>(), prop::array::uniform12(slot))
+        .prop_map(|(length, touchs)| FingerData { length, touchs })
+}
+
+fn view_data() -> impl Strategy<Value = ViewData> {
+    (any::<u32>(), any::<u32>()).prop_map(|(width, height)| ViewData { width, height })
+}
+
+fn action() -> impl Strategy<Value = ActionType> {
+    prop_oneof![
+        stylus_data().prop_map(ActionType::Stylus),
+        stylus_batch_data().prop_map(ActionType::StylusBatch),
+        finger_data().prop_map(ActionType::Finger),
+        view_data().prop_map(ActionType::Screen),
+        Just(ActionType::Heartbeat(HeartbeatData)),
+        (any::<u64>(), any::<Option<u64>>()).prop_map(|(server_time, client_time)| {
+            ActionType::Clock(ClockData {
+                server_time,
+                client_time,
+            })
+        }),
+        any::<u64>().prop_map(|sequence| ActionType::Ack(AckData { sequence })),
+        (
+            prop_oneof![
+                Just(ErrorKind::Size),
+                Just(ErrorKind::Rate),
+                Just(ErrorKind::Fields)
+            ],
+            any::<u64>()
+        )
+            .prop_map(|(kind, count)| ActionType::Error(ErrorData { kind, count })),
+        (any::<[u32; 3]>(), any::<[u32; 3]>()).prop_map(|(network, processing)| {
+            ActionType::Latency(LatencyData {
+                network,
+                processing,
+            })
+        }),
+    ]
+}
+
+fn meta() -> impl Strategy<Value = ActionMeta> {
+    (any::<Option<u64>>(), any::<Option<u64>>()).prop_map(|(timestamp, sequence)| ActionMeta {
+        timestamp,
+        sequence,
+    })
+}
+
+fn bool_text() -> impl Strategy<Value = &'static str> {
+    prop_oneof![Just("T"), Just("F")]
+}
+
+// Protocol text as PackStylusData, PackFingerData and the view update write it
+fn app_text() -> impl Strategy<Value = String> {
+    let stylus = (
+        (bool_text(), bool_text(), bool_text()),
+        prop::array::uniform5(any::<i32>()),
+    )
+        .prop_map(|((hover, down, button), numbers)| {
+            let numbers: Vec<String> = numbers.iter().map(i32::to_string).collect();
+            format!("S{hover};{down};{button};{}", numbers.join(";"))
+        });
+    // The parser fills missing touches with defaults, so the app never ends
+    // its list with one
+    let touch = touch().prop_filter("default touch", |touch| *touch != Touch::default());
+    let finger =
+        (any::<i32>(), prop::collection::vec(touch, 0..=12)).prop_map(|(length, touchs)| {
+            let mut text = format!("F{length}");
+            for touch in touchs {
+                text.push_str(&format!(
+                    ";{};{};{};{}",
+                    touch.x, touch.y, touch.slot, touch.tracking_id
+                ));
+            }
+            text
+        });
+    let view =
+        (any::<u32>(), any::<u32>()).prop_map(|(width, height)| format!("V{width};{height}"));
+    prop_oneof![stylus, finger, view, Just(String::from("H"))]
+}
+
+proptest! {
+    #[test]
+    fn encode_parse_roundtrip(action in action()) {
+        let text = action_encode(&action);
+        prop_assert_eq!(action_parse(text).map_err(TestCaseError::fail)?, action);
+    }
+
+    #[test]
+    fn meta_roundtrip(action in action(), meta in meta()) {
+        let text = action_encode_meta(&action, &meta);
+        let parsed = action_parse_meta(&text).map_err(TestCaseError::fail)?;
+        prop_assert_eq!(parsed, (action, meta));
+    }
+
+    #[test]
+    fn parse_encode_roundtrip(text in app_text()) {
+        let parsed = action_parse(text.clone()).map_err(TestCaseError::fail)?;
+        prop_assert_eq!(action_encode(&parsed), text);
+    }
+}
+
+#[test]
+fn encode_matches_app_format() {
+    let stylus = ActionType::Stylus(StylusData {
+        x: 120,
+        y: 340,
+        tilt_x: -12,
+        tilt_y: 5,
+        pressure: 2048,
+        down: true,
+        hover: true,
+        button: false,
+    });
+    assert_eq!(action_encode(&stylus), "ST;T;F;120;340;-12;5;2048");
+
+    let mut touchs = [Touch::default(); 12];
+    touchs[0] = Touch {
+        x: 10,
+        y: 20,
+        slot: 0,
+        tracking_id: 7,
+    };
+    let finger = ActionType::Finger(FingerData { length: 1, touchs });
+    assert_eq!(action_encode(&finger), "F1;10;20;0;7");
+
+    let view = ActionType::Screen(ViewData {
+        width: 1080,
+        height: 2400,
+    });
+    assert_eq!(action_encode(&view), "V1080;2400");
+
+    let heartbeat = ActionType::Heartbeat(HeartbeatData);
+    assert_eq!(action_encode(&heartbeat), "H");
+
+    let batch = ActionType::StylusBatch(StylusBatchData {
+        down: true,
+        hover: true,
+        button: false,
+        samples: vec![
+            StylusSample {
+                x: 1,
+                y: 2,
+                tilt_x: 0,
+                tilt_y: 0,
+                pressure: 100,
+                age: 8000,
+            },
+            StylusSample {
+                x: 3,
+                y: 4,
+                tilt_x: -1,
+                tilt_y: 1,
+                pressure: 200,
+                age: 0,
+            },
+        ],
+    });
+    assert_eq!(
+        action_encode(&batch),
+        "BT;T;F;2;1;2;0;0;100;8000;3;4;-1;1;200;0"
+    );
+
+    let error = ActionType::Error(ErrorData {
+        kind: ErrorKind::Rate,
+        count: 3,
+    });
+    assert_eq!(action_encode(&error), "Erate;3");
+}
+
+#[test]
+fn batch_needs_every_counted_sample() {
+    assert!(action_parse(String::from("BT;T;F;2;1;2;0;0;100;8000")).is_err());
+    let ActionType::StylusBatch(batch) = action_parse(String::from("BF;F;F;0")).unwrap() else {
+        panic!("not a batch");
+    };
+    assert!(batch.samples.is_empty());
+}
+
+#[test]
+fn encode_keeps_touches_after_empty_slot() {
+    let mut touchs = [Touch::default(); 12];
+    touchs[0] = Touch {
+        x: 5,
+        y: 5,
+        slot: -1,
+        tracking_id: 3,
+    };
+    touchs[2] = Touch {
+        x: 1,
+        y: 2,
+        slot: 4,
+        tracking_id: 9,
+    };
+    let finger = ActionType::Finger(FingerData { length: 1, touchs });
+    let text = action_encode(&finger);
+    assert_eq!(text, "F1;5;5;-1;3;0;0;-1;0;1;2;4;9");
+    assert_eq!(action_parse(text).unwrap(), finger);
+}
+
+#[test]
+fn unknown_meta_is_skipped() {
+    let (action, meta) = action_parse_meta("V10;20|x5|t42").unwrap();
+    assert_eq!(
+        action,
+        ActionType::Screen(ViewData {
+            width: 10,
+            height: 20
+        })
+    );
+    assert_eq!(meta.timestamp, Some(42));
+    assert!(action_parse_meta("V10;20|tnope").is_err());
+}
@@ -0,0 +1,87 @@
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+
+use pendroid::server::JitterBuffer;
+
+// Everything released up to now
+fn drain(buffer: &mut JitterBuffer<u32>, now: u64) -> Vec<u32> {
+    std::iter::from_fn(|| buffer.pop_due(now)).collect()
+}
+
+#[test]
+fn clumped_samples_are_paced_by_sample_time() {
+    let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+    // Sampled 8ms apart, all delivered at once 30ms after the first
+    for (index, sample_time) in [0, 8_000, 16_000, 24_000].into_iter().enumerate() {
+        buffer.push(index as u32, Some(sample_time), 30_000);
+    }
+
+    let mut releases = Vec::new();
+    while let Some(release) = buffer.next_release() {
+        assert_eq!(drain(&mut buffer, release).len(), 1);
+        releases.push(release);
+    }
+    assert!(releases[0] >= 40_000, "released early at {}", releases[0]);
+    for pair in releases.windows(2) {
+        let gap = pair[1] - pair[0];
+        assert!((4_000..=8_000).contains(&gap), "uneven gap {gap}");
+    }
+}
+
+#[test]
+fn order_is_kept_for_untimed_and_late_items() {
+    let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+    buffer.push(1, Some(100_000), 105_000);
+    buffer.push(2, None, 106_000);
+    // Sampled before the first one, but must not overtake it
+    buffer.push(3, Some(50_000), 107_000);
+
+    assert!(drain(&mut buffer, 105_000).is_empty());
+    assert_eq!(drain(&mut buffer, 1_000_000), [1, 2, 3]);
+    assert_eq!(buffer.next_release(), None);
+}
+
+#[test]
+fn jitter_raises_the_delay() {
+    let mut buffer = JitterBuffer::new(Duration::from_millis(5));
+    assert_eq!(buffer.delay(), 5_000);
+    // Transit alternating between 2ms and 42ms
+    for index in 0..200u64 {
+        let transit = if index % 2 == 0 { 2_000 } else { 42_000 };
+        buffer.push(0, Some(index * 8_000), index * 8_000 + transit);
+    }
+    assert!(buffer.delay() > 50_000, "delay {}", buffer.delay());
+    assert!(buffer.delay() <= 200_000);
+}
+
+#[test]
+fn forward_clock_jump_is_not_held_back() {
+    let mut buffer = JitterBuffer::new(Duration::from_millis(10));
+    for index in 0..20u64 {
+        buffer.push(0, Some(index * 8_000), index * 8_000 + 2_000);
+    }
+    drain(&mut buffer, 1_000_000);
+
+    // The client clock leaps an hour ahead
+    let received = 170_000;
+    buffer.push(1, Some(3_600_000_000), received);
+    let release = buffer.next_release().unwrap();
+    assert!(release <= received + 200_000, "held until {release}");
+    assert_eq!(drain(&mut buffer, received + 200_000), [1]);
+
+    // And the estimate follows the new clock right away
+    buffer.push(2, Some(3_600_008_000), received + 8_000);
+    assert!(buffer.next_release().unwrap() <= received + 8_000 + 200_000);
+}
+
+#[test]
+fn long_backlog_is_flushed() {
+    let mut buffer = JitterBuffer::new(Duration::from_millis(100));
+    for index in 0..1_025 {
+        buffer.push(index, Some(index as u64), 0);
+    }
+    // Nothing would be due for 100ms, the last one still waits
+    assert_eq!(drain(&mut buffer, 0), (0..1_024).collect::<Vec<_>>());
+    assert!(buffer.next_release().unwrap() >= 100_000);
+}
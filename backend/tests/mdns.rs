@@ -0,0 +1,39 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+
+use pendroid::server::{ServiceDetails, PROTOCOL_VERSION};
+
+fn records(details: &ServiceDetails) -> HashMap<&'static str, String> {
+    details.txt_records().into_iter().collect()
+}
+
+#[test]
+fn plain_host_records() {
+    let details = ServiceDetails {
+        hostname: String::from("desk"),
+        port: 8080,
+        pairing: false,
+        tls_fingerprint: None,
+    };
+    let records = records(&details);
+    assert_eq!(records["version"], PROTOCOL_VERSION.to_string());
+    assert_eq!(records["hostname"], "desk");
+    assert_eq!(records["pairing"], "0");
+    assert_eq!(records["tls"], "0");
+    assert!(!records.contains_key("fingerprint"));
+}
+
+#[test]
+fn tls_host_records() {
+    let details = ServiceDetails {
+        hostname: String::from("desk"),
+        port: 8080,
+        pairing: true,
+        tls_fingerprint: Some(String::from("AB:CD")),
+    };
+    let records = records(&details);
+    assert_eq!(records["pairing"], "1");
+    assert_eq!(records["tls"], "1");
+    assert_eq!(records["fingerprint"], "AB:CD");
+}
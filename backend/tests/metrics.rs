@@ -0,0 +1,90 @@
+#![cfg(target_os = "linux")]
+
+use std::time::{Duration, Instant};
+
+use futures_util::SinkExt;
+use pendroid::{
+    metrics::Metrics,
+    server::{serve_metrics, ServerConfig},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod common;
+
+async fn get(address: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET {path} HTTP/1.1\r\nHost: pendroid\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn latency_buckets_are_cumulative() {
+    let metrics = Metrics::default();
+    metrics.processing_latency(300);
+    metrics.processing_latency(2_000_000);
+    let text = metrics.render();
+    let stage = "pendroid_sample_latency_seconds_bucket{stage=\"processing\"";
+    assert!(
+        text.contains(&format!("{stage},le=\"0.00025\"}} 0\n")),
+        "{text}"
+    );
+    assert!(text.contains(&format!("{stage},le=\"0.0005\"}} 1\n")));
+    assert!(text.contains(&format!("{stage},le=\"1\"}} 1\n")));
+    assert!(text.contains(&format!("{stage},le=\"+Inf\"}} 2\n")));
+    assert!(text.contains("pendroid_sample_latency_seconds_sum{stage=\"processing\"} 2.0003\n"));
+}
+
+#[test]
+fn stylus_rate_is_the_last_whole_second() {
+    let metrics = Metrics::default();
+    let start = Instant::now();
+    metrics.stylus_samples(3, start);
+    metrics.stylus_samples(2, start);
+    assert_eq!(metrics.stylus_rate(start), 0);
+    assert_eq!(metrics.stylus_rate(start + Duration::from_secs(1)), 5);
+    assert_eq!(metrics.stylus_rate(start + Duration::from_secs(3)), 0);
+}
+
+#[tokio::test]
+async fn endpoint_counts_messages_and_errors() {
+    let (url, _log) = common::start(ServerConfig::default()).await;
+    let metrics = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = metrics.local_addr().unwrap().to_string();
+    tokio::spawn(serve_metrics(metrics));
+
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    for text in ["ST;T;F;1;1;0;0;100", "X1;2", "ST;T;F;1;1;0;0;hard"] {
+        socket.send(Message::text(text)).await.unwrap();
+    }
+
+    let expected = [
+        "pendroid_messages_total{type=\"S\"} 1\n",
+        "pendroid_parse_errors_total{field=\"header\"} 1\n",
+        "pendroid_parse_errors_total{field=\"pressure\"} 1\n",
+        "pendroid_connections{transport=\"websocket\"} 1\n",
+        "pendroid_sample_latency_seconds_count{stage=\"processing\"} 1\n",
+    ];
+    let mut response = String::new();
+    for _ in 0..200 {
+        response = get(&address, "/metrics").await;
+        if expected.iter().all(|line| response.contains(line)) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    for line in expected {
+        assert!(response.contains(line), "{line} missing from {response}");
+    }
+
+    assert!(get(&address, "/")
+        .await
+        .starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
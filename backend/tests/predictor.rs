@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use pendroid::{StylusData, StylusPredictor};
+
+fn pen(x: i32, y: i32, down: bool) -> StylusData {
+    StylusData {
+        x,
+        y,
+        tilt_x: 0,
+        tilt_y: 0,
+        pressure: if down { 2048 } else { 0 },
+        down,
+        hover: true,
+        button: false,
+    }
+}
+
+// Feed samples 8ms apart, returns the reported positions
+fn run(predictor: &mut StylusPredictor, samples: &[StylusData]) -> Vec<(i32, i32)> {
+    samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| predictor.predict(sample, index as u64 * 8_000))
+        .collect()
+}
+
+#[test]
+fn straight_line_is_led() {
+    let mut predictor = StylusPredictor::new(Duration::from_millis(16));
+    let samples: Vec<_> = (0..5).map(|i| pen(i * 10, 100, true)).collect();
+    let reported = run(&mut predictor, &samples);
+    // Not enough history for the first two
+    assert_eq!(reported[..2], [(0, 100), (10, 100)]);
+    // Constant velocity, two samples ahead
+    assert_eq!(reported[2..], [(40, 100), (50, 100), (60, 100)]);
+}
+
+#[test]
+fn transitions_restart_prediction() {
+    let mut predictor = StylusPredictor::new(Duration::from_millis(16));
+    let mut samples: Vec<_> = (0..4).map(|i| pen(i * 10, 100, true)).collect();
+    // Pen-up and the hover samples after it are reported as received
+    samples.extend((4..6).map(|i| pen(i * 10, 100, false)));
+    let reported = run(&mut predictor, &samples);
+    assert_eq!(reported[4..], [(40, 100), (50, 100)]);
+}
+
+#[test]
+fn sharp_turn_is_not_predicted() {
+    let mut predictor = StylusPredictor::new(Duration::from_millis(16));
+    let samples = [
+        pen(0, 0, true),
+        pen(10, 0, true),
+        pen(20, 0, true),
+        pen(20, 10, true),
+    ];
+    let reported = run(&mut predictor, &samples);
+    assert_eq!(reported[3], (20, 10));
+}
+
+#[test]
+fn clumped_samples_are_not_predicted() {
+    let mut predictor = StylusPredictor::new(Duration::from_millis(16));
+    predictor.predict(&pen(0, 0, true), 0);
+    predictor.predict(&pen(10, 0, true), 8_000);
+    // Arrived with the previous one, no usable velocity
+    assert_eq!(predictor.predict(&pen(20, 0, true), 8_100), (20, 0));
+}
@@ -0,0 +1,99 @@
+#![cfg(target_os = "linux")]
+
+use std::{net::UdpSocket, sync::Arc};
+
+use pendroid::server::{
+    restrict, serve_tcp_lines, Account, ClientPolicy, ServerConfig, WorkerHandle,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+mod common;
+
+// A heartbeat through the backend thread and the async runtime
+async fn heartbeat_round_trip() -> Result<String, String> {
+    let devices = common::create_devices(&common::Log::default());
+    let worker = WorkerHandle::spawn_with(ClientPolicy::Takeover, devices)?;
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|err| err.to_string())?;
+    let address = listener.local_addr().map_err(|err| err.to_string())?;
+    let config = Arc::new(ServerConfig::default());
+    tokio::spawn(serve_tcp_lines(listener, worker, config));
+
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    writer
+        .write_all(b"H\n")
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut lines = BufReader::new(reader).lines();
+    lines
+        .next_line()
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| String::from("closed"))
+}
+
+#[test]
+fn accounts_resolve_by_name() {
+    let root = Account::lookup("root", None).unwrap();
+    assert_eq!(root, Account { uid: 0, gid: 0 });
+    assert!(Account::lookup("pendroid-no-such-user", None).is_err());
+    assert!(Account::lookup("root", Some("pendroid-no-such-group")).is_err());
+}
+
+// What a sandboxed process can still do, as an exit code
+fn sandboxed_child() -> i32 {
+    if restrict(&[]).is_err() {
+        return 1;
+    }
+    if std::fs::File::open("/etc/passwd").is_ok() {
+        return 2;
+    }
+    // Sockets keep working
+    let Ok(socket) = UdpSocket::bind("127.0.0.1:0") else {
+        return 3;
+    };
+    let Ok(address) = socket.local_addr() else {
+        return 3;
+    };
+    let mut buffer = [0u8; 4];
+    if !matches!(
+        socket
+            .send_to(b"H", address)
+            .and_then(|_| socket.recv(&mut buffer)),
+        Ok(1)
+    ) {
+        return 4;
+    }
+    // So do threads and the server itself
+    let Ok(runtime) = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+    else {
+        return 5;
+    };
+    match runtime.block_on(heartbeat_round_trip()) {
+        Ok(reply) if reply == "H" => 0,
+        _ => 6,
+    }
+}
+
+#[test]
+fn sandbox_denies_files_but_keeps_serving() {
+    // The sandbox cannot be lifted, so it is tried in a child process
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        unsafe { libc::_exit(sandboxed_child()) };
+    }
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(libc::WEXITSTATUS(status), 0);
+}
@@ -0,0 +1,74 @@
+use pendroid::{action_encode, script, ActionType};
+
+fn encode_all(actions: &[ActionType]) -> Vec<String> {
+    actions.iter().map(action_encode).collect()
+}
+
+#[test]
+fn tap_downs_and_lifts_one_finger() {
+    assert_eq!(
+        encode_all(&script::tap((10, 20), 1)),
+        ["F1;10;20;0;1", "F0;-1;-1;0;-1"]
+    );
+}
+
+#[test]
+fn swipe_centers_fingers_on_the_line() {
+    let actions = encode_all(&script::swipe(3, (500, 100), (500, 900), 200, 4, 7));
+    assert_eq!(actions.len(), 6);
+    assert_eq!(actions[0], "F3;300;100;0;7;500;100;1;8;700;100;2;9");
+    assert_eq!(actions[2], "F3;300;500;0;7;500;500;1;8;700;500;2;9");
+    assert_eq!(actions[4], "F3;300;900;0;7;500;900;1;8;700;900;2;9");
+    assert_eq!(actions[5], "F0;-1;-1;0;-1;-1;-1;1;-1;-1;-1;2;-1");
+}
+
+#[test]
+fn swipe_with_even_fingers_stays_symmetric() {
+    let actions = encode_all(&script::swipe(2, (0, 0), (0, 100), 200, 1, 1));
+    assert_eq!(actions[0], "F2;-100;0;0;1;100;0;1;2");
+    assert_eq!(actions[1], "F2;-100;100;0;1;100;100;1;2");
+}
+
+#[test]
+fn pinch_moves_between_radii() {
+    let actions = encode_all(&script::pinch((1000, 800), 300, 100, 2, 1));
+    assert_eq!(
+        actions,
+        [
+            "F2;700;800;0;1;1300;800;1;2",
+            "F2;800;800;0;1;1200;800;1;2",
+            "F2;900;800;0;1;1100;800;1;2",
+            "F0;-1;-1;0;-1;-1;-1;1;-1",
+        ]
+    );
+}
+
+#[test]
+fn paths_hit_their_endpoints() {
+    let line = script::line((0, 0), (100, -50), 10);
+    assert_eq!(line.len(), 11);
+    assert_eq!((line[0], line[10]), ((0, 0), (100, -50)));
+
+    let circle = script::circle((50, 50), 10, 8);
+    assert_eq!(circle[0], (60, 50));
+    assert_eq!(circle[2], (50, 60));
+    assert_eq!(circle[8], (60, 50));
+
+    let bezier = script::bezier([(0, 0), (0, 100), (100, 100), (100, 0)], 2);
+    assert_eq!(bezier, [(0, 0), (50, 75), (100, 0)]);
+}
+
+#[test]
+fn stylus_stroke_hovers_around_the_path() {
+    let path = script::line((0, 0), (10, 10), 1);
+    assert_eq!(
+        encode_all(&script::stylus_stroke(&path, 2048)),
+        [
+            "ST;F;F;0;0;0;0;0",
+            "ST;T;F;0;0;0;0;2048",
+            "ST;T;F;10;10;0;0;2048",
+            "ST;F;F;10;10;0;0;0",
+            "SF;F;F;10;10;0;0;0",
+        ]
+    );
+}
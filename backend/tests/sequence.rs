@@ -0,0 +1,48 @@
+#![cfg(target_os = "linux")]
+
+use pendroid::{
+    action_parse,
+    server::{Arrival, Contacts, SequenceTracker},
+};
+
+#[test]
+fn arrivals_are_classified() {
+    let mut tracker = SequenceTracker::default();
+    let arrivals: Vec<_> = [5, 6, 9, 8, 8, 6, 10, 200, 100]
+        .into_iter()
+        .map(|sequence| tracker.arrive(sequence))
+        .collect();
+    assert_eq!(
+        arrivals,
+        [
+            Arrival::InOrder,
+            Arrival::InOrder,
+            Arrival::Gap(2),
+            Arrival::Reordered,
+            Arrival::Duplicate,
+            Arrival::Duplicate,
+            Arrival::InOrder,
+            Arrival::Gap(189),
+            // Too old to remember
+            Arrival::Reordered,
+        ]
+    );
+}
+
+#[test]
+fn only_unchanged_contacts_are_confirmed() {
+    let parse = |text: &str| action_parse(String::from(text)).unwrap();
+    let mut contacts = Contacts::default();
+    // Nothing is pressed yet, so nothing can get stuck
+    assert!(contacts.confirmed_by(&parse("H")));
+
+    contacts.update(&parse("ST;T;F;1;1;0;0;100"));
+    assert!(contacts.confirmed_by(&parse("ST;T;F;9;9;0;0;300")));
+    assert!(!contacts.confirmed_by(&parse("ST;F;F;9;9;0;0;0")));
+    assert!(!contacts.confirmed_by(&parse("F0")));
+
+    contacts.update(&parse("SF;F;F;1;1;0;0;0"));
+    contacts.update(&parse("F1;1;1;0;5"));
+    assert!(contacts.confirmed_by(&parse("F1;7;7;0;5")));
+    assert!(!contacts.confirmed_by(&parse("F1;7;7;0;6")));
+}
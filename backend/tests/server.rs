@@ -0,0 +1,412 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{spawn_worker, start, texts, wait_for};
+
+use futures_util::{SinkExt, StreamExt};
+use pendroid::{
+    action_encode, action_parse, action_parse_meta,
+    server::{
+        serve_tcp_lines, serve_udp, serve_unix_lines, AuditLog, ClientPolicy, Heartbeat, Limits,
+        ServerConfig,
+    },
+    ActionType, ClockData, LatencyData,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, UdpSocket, UnixListener, UnixStream},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Error, Message},
+};
+
+fn heartbeat_config() -> ServerConfig {
+    ServerConfig {
+        policy: ClientPolicy::Takeover,
+        heartbeat: Heartbeat {
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_millis(200),
+        },
+        ..ServerConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn heartbeat_is_echoed() {
+    let (url, log) = start(heartbeat_config()).await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket.send(Message::text("H")).await.unwrap();
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) if text.starts_with('C') => {}
+            Message::Text(text) => {
+                assert_eq!(text.as_str(), "H");
+                break;
+            }
+            Message::Ping(_) => {}
+            message => panic!("unexpected {message:?}"),
+        }
+    }
+    wait_for(&log, &["H"]).await;
+}
+
+#[tokio::test]
+async fn silent_client_is_disconnected_and_released() {
+    let (url, log) = start(heartbeat_config()).await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket
+        .send(Message::text("ST;T;F;1;1;0;0;100"))
+        .await
+        .unwrap();
+
+    // Never reading means pings go unanswered
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    wait_for(&log, &["ST;T;F;1;1;0;0;100", "reset"]).await;
+}
+
+#[tokio::test]
+async fn responsive_client_stays_connected() {
+    let (url, log) = start(heartbeat_config()).await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    // Reading answers pings with pongs
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(400);
+    while let Ok(Some(message)) = tokio::time::timeout_at(deadline, socket.next()).await {
+        message.unwrap();
+    }
+    socket.send(Message::text("H")).await.unwrap();
+    wait_for(&log, &["H"]).await;
+}
+
+#[tokio::test]
+async fn latency_is_reported_from_client_timestamps() {
+    let (url, log) = start(ServerConfig {
+        report_interval: Duration::from_millis(300),
+        ..heartbeat_config()
+    })
+    .await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+
+    // The client clock runs one second ahead of the server
+    let mut sample = None;
+    loop {
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            continue;
+        };
+        match action_parse(text.to_string()).unwrap() {
+            ActionType::Clock(ClockData { server_time, .. }) => {
+                let client_time = server_time + 1_000_000;
+                let reply = ActionType::Clock(ClockData {
+                    server_time,
+                    client_time: Some(client_time),
+                });
+                socket
+                    .send(Message::text(action_encode(&reply)))
+                    .await
+                    .unwrap();
+                if sample.is_none() {
+                    let text = format!("ST;F;F;1;1;0;0;0|t{client_time}");
+                    socket.send(Message::text(text.as_str())).await.unwrap();
+                    sample = Some(server_time);
+                }
+            }
+            ActionType::Latency(LatencyData {
+                network,
+                processing,
+            }) => {
+                // Sent right after the probe, so well under a second late
+                assert!(network[0] < 500_000, "network latency {network:?}");
+                assert!(processing[0] < 500_000, "processing latency {processing:?}");
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    // The sample time reaches the devices on the server clock
+    let logged = texts(&log)[0].clone();
+    let (_, meta) = action_parse_meta(&logged).unwrap();
+    let sent = sample.unwrap();
+    let sample_time = meta.timestamp.unwrap();
+    assert!(
+        (sent..sent + 500_000).contains(&sample_time),
+        "sample time {sample_time}, probe sent at {sent}"
+    );
+}
+
+#[tokio::test]
+async fn jitter_buffer_forwards_in_order() {
+    let (url, log) = start(ServerConfig {
+        jitter_buffer: Some(Duration::from_millis(20)),
+        ..heartbeat_config()
+    })
+    .await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    for text in ["V10;10", "ST;F;F;1;1;0;0;0", "SF;F;F;1;1;0;0;0"] {
+        socket.send(Message::text(text)).await.unwrap();
+    }
+    wait_for(&log, &["V10;10", "ST;F;F;1;1;0;0;0", "SF;F;F;1;1;0;0;0"]).await;
+}
+
+#[tokio::test]
+async fn lost_transition_resyncs_devices() {
+    let (url, log) = start(heartbeat_config()).await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    // n2, the pen-up, is lost and n3 is delivered twice
+    for text in [
+        "ST;T;F;1;1;0;0;100|n1",
+        "SF;F;F;2;2;0;0;0|n3",
+        "SF;F;F;2;2;0;0;0|n3",
+        "V10;10|n4",
+    ] {
+        socket.send(Message::text(text)).await.unwrap();
+    }
+    wait_for(
+        &log,
+        &["ST;T;F;1;1;0;0;100", "reset", "SF;F;F;2;2;0;0;0", "V10;10"],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn udp_datagrams_are_acknowledged_and_stale_ones_dropped() {
+    let config = heartbeat_config();
+    let (worker, log) = spawn_worker(config.policy);
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = server.local_addr().unwrap();
+    tokio::spawn(serve_udp(server, worker, Arc::new(config)));
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(address).await.unwrap();
+    let mut buffer = [0u8; 256];
+    let mut replies = Vec::new();
+    for datagram in [
+        "ST;T;F;5;5;0;0;100|n2",
+        // Overtaken by n2, so stale
+        "ST;T;F;4;4;0;0;100|n1",
+        "H|n3",
+        // Resent transition, already applied
+        "ST;T;F;5;5;0;0;100|n2",
+        "unsequenced",
+        "SF;F;F;6;6;0;0;0|n4",
+    ] {
+        socket.send(datagram.as_bytes()).await.unwrap();
+    }
+    // Every sequenced datagram is acknowledged, the heartbeat echoed too
+    while replies.len() < 6 {
+        let length = tokio::time::timeout(Duration::from_secs(1), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let reply = String::from_utf8_lossy(&buffer[..length]).to_string();
+        if !reply.starts_with('C') {
+            replies.push(reply);
+        }
+    }
+    assert_eq!(replies, ["A2", "A1", "A3", "H", "A2", "A4"]);
+    wait_for(&log, &["ST;T;F;5;5;0;0;100", "H", "SF;F;F;6;6;0;0;0"]).await;
+
+    // Silent peers are released like WebSocket clients
+    wait_for(
+        &log,
+        &["ST;T;F;5;5;0;0;100", "H", "SF;F;F;6;6;0;0;0", "reset"],
+    )
+    .await;
+}
+
+// Send lines, then wait for the heartbeat echo and hang up
+async fn exchange_lines(stream: impl AsyncRead + AsyncWrite, lines: &str) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    writer.write_all(lines.as_bytes()).await.unwrap();
+    let mut replies = BufReader::new(reader).lines();
+    loop {
+        let reply = replies.next_line().await.unwrap().unwrap();
+        if reply == "H" {
+            break;
+        }
+        assert!(reply.starts_with('C'), "unexpected reply {reply}");
+    }
+}
+
+#[tokio::test]
+async fn tcp_lines_reach_the_devices() {
+    let config = heartbeat_config();
+    let (worker, log) = spawn_worker(config.policy);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve_tcp_lines(listener, worker, Arc::new(config)));
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    exchange_lines(stream, "ST;T;F;1;1;0;0;100\r\n\nSF;F;F;1;1;0;0;0\nH\n").await;
+    // Hanging up releases the devices
+    wait_for(
+        &log,
+        &["ST;T;F;1;1;0;0;100", "SF;F;F;1;1;0;0;0", "H", "reset"],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn unix_lines_reach_the_devices() {
+    let config = heartbeat_config();
+    let (worker, log) = spawn_worker(config.policy);
+    let path = std::env::temp_dir().join(format!("pendroid-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(serve_unix_lines(listener, worker, Arc::new(config)));
+
+    let stream = UnixStream::connect(&path).await.unwrap();
+    exchange_lines(stream, "V10;10\nH\n").await;
+    wait_for(&log, &["V10;10", "H", "reset"]).await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn browser_origins_need_the_allowlist() {
+    let (url, log) = start(ServerConfig {
+        allowed_origins: vec![String::from("https://pendroid.example")],
+        ..ServerConfig::default()
+    })
+    .await;
+    let with_origin = |origin: &'static str| {
+        let mut request = url.as_str().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Origin", HeaderValue::from_static(origin));
+        request
+    };
+
+    match connect_async(with_origin("https://evil.example")).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 403),
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(connect_async(with_origin("null")).await.is_err());
+
+    let (mut socket, _) = connect_async(with_origin("https://pendroid.example"))
+        .await
+        .unwrap();
+    socket.send(Message::text("H")).await.unwrap();
+    wait_for(&log, &["H"]).await;
+}
+
+fn strict_limits() -> Limits {
+    Limits {
+        max_message: 64,
+        rate: 5,
+        max_fields: 8,
+        max_violations: 3,
+    }
+}
+
+#[tokio::test]
+async fn flooding_client_gets_errors_then_is_dropped() {
+    let (url, log) = start(ServerConfig {
+        limits: strict_limits(),
+        ..ServerConfig::default()
+    })
+    .await;
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket
+        .send(Message::text("ST;T;F;1;1;0;0;100"))
+        .await
+        .unwrap();
+    socket.send(Message::text("H".repeat(100))).await.unwrap();
+    socket
+        .send(Message::text("F1;1;1;0;5;1;1;1;1"))
+        .await
+        .unwrap();
+    for _ in 0..10 {
+        // The server may hang up while these are still being sent
+        let _ = socket.send(Message::text("H")).await;
+    }
+
+    let mut replies = Vec::new();
+    while let Some(Ok(message)) = socket.next().await {
+        match message {
+            Message::Text(text) => replies.push(text.to_string()),
+            Message::Close(Some(frame)) => {
+                assert_eq!(frame.reason.as_str(), "too many rejected messages");
+            }
+            _ => {}
+        }
+    }
+    // Two tokens left for heartbeats, then the rate is exceeded
+    assert_eq!(
+        replies,
+        ["Esize;1", "Efields;2", "H", "H", "Erate;3", "Erate;4"]
+    );
+    // Disconnecting released the pen
+    wait_for(&log, &["ST;T;F;1;1;0;0;100", "H", "H", "reset"]).await;
+}
+
+#[tokio::test]
+async fn long_lines_are_rejected_without_buffering() {
+    let config = ServerConfig {
+        limits: strict_limits(),
+        ..heartbeat_config()
+    };
+    let (worker, log) = spawn_worker(config.policy);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve_tcp_lines(listener, worker, Arc::new(config)));
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    let (reader, mut writer) = tokio::io::split(stream);
+    let long = format!("F{}\nV1;1\n", "1;".repeat(10_000));
+    writer.write_all(long.as_bytes()).await.unwrap();
+    let mut replies = BufReader::new(reader).lines();
+    assert_eq!(replies.next_line().await.unwrap().unwrap(), "Esize;1");
+    wait_for(&log, &["V1;1"]).await;
+}
+
+#[tokio::test]
+async fn connections_are_audited() {
+    let path = std::env::temp_dir().join(format!("pendroid-audit-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (url, log) = start(ServerConfig {
+        audit: Some(Arc::new(AuditLog::open(&path, 1 << 20, 1).unwrap())),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let mut request = format!("{url}?label=tablet&version=1")
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("Origin", HeaderValue::from_static("https://evil.example"));
+    assert!(connect_async(request).await.is_err());
+
+    let (mut socket, _) = connect_async(format!("{url}?label=tablet&version=1"))
+        .await
+        .unwrap();
+    socket.send(Message::text("H")).await.unwrap();
+    wait_for(&log, &["H"]).await;
+    socket.close(None).await.unwrap();
+    drop(socket);
+
+    let mut lines = Vec::new();
+    for _ in 0..200 {
+        lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if lines.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    assert!(lines[0].contains("\"origin\":\"https://evil.example\""));
+    assert!(lines[0].contains("\"accepted\":false"));
+    assert!(lines[0].contains("\"reason\":\"rejected connection from origin"));
+    assert!(lines[1].contains("\"transport\":\"websocket\""));
+    assert!(lines[1].contains("\"label\":\"tablet\",\"version\":\"1\""));
+    assert!(lines[1].contains("\"accepted\":true,\"messages\":1,\"rejected\":0"));
+    assert!(lines[1].contains("\"reason\":\"closed\""));
+}
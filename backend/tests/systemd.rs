@@ -0,0 +1,36 @@
+#![cfg(target_os = "linux")]
+
+use std::{os::unix::net::UnixDatagram, path::PathBuf};
+
+use pendroid::server::Notifier;
+
+fn receive(socket: &UnixDatagram) -> String {
+    let mut buffer = [0u8; 64];
+    let length = socket.recv(&mut buffer).unwrap();
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+#[test]
+fn notifies_path_socket() {
+    let path = std::env::temp_dir().join(format!("pendroid-notify-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    let notifier = Notifier::new(path.clone()).unwrap();
+    notifier.notify("READY=1").unwrap();
+    assert_eq!(receive(&socket), "READY=1");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn notifies_abstract_socket() {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let name = format!("pendroid-notify-{}", std::process::id());
+    let address = SocketAddr::from_abstract_name(&name).unwrap();
+    let socket = UnixDatagram::bind_addr(&address).unwrap();
+
+    let notifier = Notifier::new(PathBuf::from(format!("@{name}"))).unwrap();
+    notifier.notify("WATCHDOG=1").unwrap();
+    assert_eq!(receive(&socket), "WATCHDOG=1");
+}
@@ -0,0 +1,20 @@
+#![cfg(target_os = "linux")]
+
+use std::time::{Duration, Instant};
+
+use pendroid::server::Throttle;
+
+#[test]
+fn repeats_are_counted_until_the_next_window() {
+    let mut throttle = Throttle::new(2, Duration::from_secs(1));
+    let start = Instant::now();
+    assert_eq!(throttle.allow(start), Some(0));
+    assert_eq!(throttle.allow(start), Some(0));
+    assert_eq!(throttle.allow(start), None);
+    assert_eq!(throttle.allow(start + Duration::from_millis(500)), None);
+
+    let later = start + Duration::from_secs(1);
+    assert_eq!(throttle.allow(later), Some(2));
+    assert_eq!(throttle.allow(later), Some(0));
+    assert_eq!(throttle.allow(later), None);
+}
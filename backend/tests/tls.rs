@@ -0,0 +1,140 @@
+#![cfg(target_os = "linux")]
+
+use std::{os::unix::fs::PermissionsExt, sync::Arc, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use pendroid::server::{fingerprint, serve_tls_listener, ServerConfig, TlsIdentity};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        DigitallySignedStruct, SignatureScheme,
+    },
+    TlsConnector,
+};
+use tokio_tungstenite::{client_async, tungstenite::Message};
+
+mod common;
+
+// Trusts exactly one certificate, as the app does after pairing
+#[derive(Debug)]
+struct PinnedCert {
+    fingerprint: String,
+    provider: CryptoProvider,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        _: &[CertificateDer],
+        _: &ServerName,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(String::from("fingerprint mismatch")))
+        }
+    }
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, algorithms)
+    }
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn connector(fingerprint: &str) -> TlsConnector {
+    let verifier = PinnedCert {
+        fingerprint: String::from(fingerprint),
+        provider: ring::default_provider(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pendroid-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn certificate_is_persisted() {
+    let dir = temp_dir("persist");
+    let first = TlsIdentity::load_or_create(&dir).unwrap();
+    let second = TlsIdentity::load_or_create(&dir).unwrap();
+    assert_eq!(first.fingerprint, second.fingerprint);
+    // 32 bytes as AB:CD:...
+    assert_eq!(first.fingerprint.len(), 32 * 3 - 1);
+
+    let key = std::fs::metadata(dir.join("key.pem")).unwrap();
+    assert_eq!(key.permissions().mode() & 0o777, 0o600);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn pinned_client_reaches_the_devices() {
+    let dir = temp_dir("wss");
+    let identity = TlsIdentity::load_or_create(&dir).unwrap();
+    let pinned = identity.fingerprint.clone();
+
+    let config = ServerConfig::default();
+    let (worker, log) = common::spawn_worker(config.policy);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve_tls_listener(listener, worker, config, identity));
+
+    // Another certificate is refused
+    let name = ServerName::try_from("pendroid").unwrap();
+    let stream = TcpStream::connect(address).await.unwrap();
+    let wrong = connector("00:11").connect(name.clone(), stream).await;
+    assert!(wrong.is_err());
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    let stream = connector(&pinned).connect(name, stream).await.unwrap();
+    let (mut socket, _) = client_async(format!("wss://{address}/"), stream)
+        .await
+        .unwrap();
+    socket.send(Message::text("H")).await.unwrap();
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(1), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        if message == Message::text("H") {
+            break;
+        }
+    }
+    // The backend thread applies it shortly after the echo
+    common::wait_for(&log, &["H"]).await;
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,220 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{view, wait_for_tagged, Log, MockDevices};
+use pendroid::{
+    action_parse,
+    server::{ClientPolicy, WorkerHandle},
+    DeviceIdentity,
+};
+
+// Device sets are tagged with their identity index, creating one logs its
+// stylus name
+fn spawn(policy: ClientPolicy) -> (WorkerHandle, Log) {
+    let log = Log::default();
+    let create_log = log.clone();
+    let worker = WorkerHandle::spawn_with(
+        policy,
+        Box::new(move |identity: &DeviceIdentity| {
+            let name = identity.device_name("pendroid-stylus");
+            create_log.lock().unwrap().push((identity.index, name));
+            Ok(MockDevices::new(identity.index, create_log.clone()))
+        }),
+    )
+    .unwrap();
+    (worker, log)
+}
+
+#[tokio::test]
+async fn reject_refuses_second_client() {
+    let (worker, log) = spawn(ClientPolicy::Reject);
+    let first = worker.connect(None).await.unwrap();
+    assert!(worker.connect(None).await.is_err());
+
+    first.send(view(1), Instant::now(), None).await;
+    wait_for_tagged(&log, &[(0, "pendroid-stylus"), (0, "V1;1")]).await;
+
+    // The devices are free again once the owner leaves
+    drop(first);
+    let second = worker.connect(None).await.unwrap();
+    second.send(view(2), Instant::now(), None).await;
+    wait_for_tagged(
+        &log,
+        &[
+            (0, "pendroid-stylus"),
+            (0, "V1;1"),
+            (0, "reset"),
+            (0, "V2;2"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn takeover_kicks_and_resets_previous_owner() {
+    let (worker, log) = spawn(ClientPolicy::Takeover);
+    let mut first = worker.connect(None).await.unwrap();
+    first.send(view(1), Instant::now(), None).await;
+
+    let second = worker.connect(None).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), first.kicked())
+        .await
+        .expect("previous owner was not kicked");
+    // Safe to wait again after being kicked
+    first.kicked().await;
+
+    // The old client no longer reaches the devices
+    first.send(view(3), Instant::now(), None).await;
+    second.send(view(2), Instant::now(), None).await;
+    wait_for_tagged(
+        &log,
+        &[
+            (0, "pendroid-stylus"),
+            (0, "V1;1"),
+            (0, "reset"),
+            (0, "V2;2"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn disconnect_releases_state() {
+    let (worker, log) = spawn(ClientPolicy::Takeover);
+    let session = worker.connect(None).await.unwrap();
+    session.send(view(4), Instant::now(), None).await;
+    drop(session);
+    wait_for_tagged(&log, &[(0, "pendroid-stylus"), (0, "V4;4"), (0, "reset")]).await;
+}
+
+#[tokio::test]
+async fn per_client_devices_are_named_and_torn_down() {
+    let (worker, log) = spawn(ClientPolicy::PerClient);
+    let first = worker.connect(Some(String::from("alice"))).await.unwrap();
+    let second = worker.connect(Some(String::from("b%20ob!"))).await.unwrap();
+    first.send(view(1), Instant::now(), None).await;
+    second.send(view(2), Instant::now(), None).await;
+    wait_for_tagged(
+        &log,
+        &[
+            (1, "pendroid-stylus-1-alice"),
+            (2, "pendroid-stylus-2-b20ob"),
+            (1, "V1;1"),
+            (2, "V2;2"),
+        ],
+    )
+    .await;
+
+    // Released, destroyed, and the index is reused by the next client
+    drop(second);
+    let third = worker.connect(None).await.unwrap();
+    drop(third);
+    wait_for_tagged(
+        &log,
+        &[
+            (1, "pendroid-stylus-1-alice"),
+            (2, "pendroid-stylus-2-b20ob"),
+            (1, "V1;1"),
+            (2, "V2;2"),
+            (2, "reset"),
+            (2, "drop"),
+            (2, "pendroid-stylus-2"),
+            (2, "reset"),
+            (2, "drop"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn backlog_coalesces_moves_but_keeps_transitions() {
+    let (worker, log) = spawn(ClientPolicy::Takeover);
+    let session = worker.connect(None).await.unwrap();
+    let mut lines = vec![String::from("V0;0")];
+    lines.extend((1..=20).map(|x| format!("ST;F;F;{x};0;0;0;0")));
+    lines.extend((21..=25).map(|x| format!("ST;T;F;{x};0;0;0;100")));
+    lines.extend((26..=30).map(|x| format!("ST;F;F;{x};0;0;0;0")));
+    lines.push(String::from("F1;1;1;0;5"));
+    lines.extend((2..=10).map(|x| format!("F1;{x};{x};0;5")));
+    lines.push(String::from("F0;-1;-1;0;-1"));
+    for line in &lines {
+        let action = action_parse(line.clone()).unwrap();
+        session.send(action, Instant::now(), None).await;
+    }
+
+    // Pen down samples and every first and last sample of a run remain
+    let mut expected = vec![(0, "pendroid-stylus"), (0, "V0;0")];
+    let down: Vec<String> = (21..=25).map(|x| format!("ST;T;F;{x};0;0;0;100")).collect();
+    expected.extend([(0, "ST;F;F;1;0;0;0;0"), (0, "ST;F;F;20;0;0;0;0")]);
+    expected.extend(down.iter().map(|line| (0, line.as_str())));
+    expected.extend([
+        (0, "ST;F;F;26;0;0;0;0"),
+        (0, "ST;F;F;30;0;0;0;0"),
+        (0, "F1;1;1;0;5"),
+        (0, "F1;10;10;0;5"),
+        (0, "F0;-1;-1;0;-1"),
+    ]);
+    wait_for_tagged(&log, &expected).await;
+    assert_eq!(session.stats().lock().unwrap().coalesced, 18 + 3 + 8);
+}
+
+#[tokio::test]
+async fn shutdown_releases_and_destroys_every_device() {
+    let (worker, log) = spawn(ClientPolicy::PerClient);
+    let mut session = worker.connect(None).await.unwrap();
+    session.send(view(1), Instant::now(), None).await;
+    assert!(worker.alive().await);
+
+    // The devices are gone once shutdown returns
+    worker.shutdown().await;
+    let expected = [
+        (1, "pendroid-stylus-1"),
+        (1, "V1;1"),
+        (1, "reset"),
+        (1, "drop"),
+    ]
+    .map(|(index, text)| (index, String::from(text)));
+    assert_eq!(*log.lock().unwrap(), expected);
+    tokio::time::timeout(Duration::from_secs(1), session.kicked())
+        .await
+        .expect("client was not dropped");
+    assert!(!worker.alive().await);
+    assert!(worker.connect(None).await.is_err());
+}
+
+#[tokio::test]
+async fn per_client_indices_are_bounded() {
+    let (worker, _log) = spawn(ClientPolicy::PerClient);
+    let mut sessions = Vec::new();
+    for _ in 0..DeviceIdentity::MAX_INDEX {
+        sessions.push(worker.connect(None).await.unwrap());
+    }
+    assert!(worker.connect(None).await.is_err());
+
+    // A freed index is handed out again
+    sessions.pop();
+    assert!(worker.connect(None).await.is_ok());
+}
+
+#[test]
+fn identities_have_distinct_product_ids() {
+    let shared = DeviceIdentity::default();
+    let first = DeviceIdentity::new(1, Some("alice"));
+    assert_eq!(shared.product_id(1332), Ok(1332));
+    assert_eq!(first.product_id(1332), Ok(1334));
+    assert_eq!(first.product_id(1333), Ok(1335));
+    let last = DeviceIdentity::new(DeviceIdentity::MAX_INDEX, None);
+    assert_eq!(
+        last.product_id(1333),
+        Ok(1333 + 2 * DeviceIdentity::MAX_INDEX)
+    );
+    // Past the limit the ids would wrap onto another set
+    assert!(DeviceIdentity::new(DeviceIdentity::MAX_INDEX + 1, None)
+        .product_id(1332)
+        .is_err());
+    assert!(DeviceIdentity::new(1, None).product_id(u16::MAX).is_err());
+    assert_eq!(DeviceIdentity::new(3, Some("???")).label, None);
+}